@@ -0,0 +1,82 @@
+use std::fs::{create_dir_all, remove_dir, File};
+use std::io;
+use std::os::unix::io::{AsRawFd, RawFd};
+use std::path::{Path, PathBuf};
+
+pub const DEFAULT_CGROUP_ROOT: &str = "/sys/fs/cgroup/genome-benchmark-cgroup";
+
+/// A cgroup v2 used to enforce a hard memory limit on a single run
+pub struct MemoryCgroup {
+    path: PathBuf,
+    procs_file: File,
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+pub struct MemoryEvents {
+    pub oom: u64,
+    pub oom_kill: u64,
+}
+
+impl MemoryCgroup {
+    /// Creates a new cgroup under `root`, that must be a delegated cgroup v2 directory.
+    /// Swap is disabled when supported, so that the limit cannot be circumvented by swapping
+    pub fn create(root: impl AsRef<Path>, name: &str, limit_bytes: u64) -> io::Result<Self> {
+        let root = root.as_ref();
+        create_dir_all(root)?;
+
+        // Enable the memory controller for the children, fails harmlessly if already enabled
+        let _ = std::fs::write(root.join("cgroup.subtree_control"), "+memory");
+
+        let path = root.join(name);
+        if !path.exists() {
+            std::fs::create_dir(&path)?;
+        }
+
+        std::fs::write(path.join("memory.max"), limit_bytes.to_string())?;
+        let _ = std::fs::write(path.join("memory.swap.max"), "0");
+
+        let procs_file = File::options()
+            .write(true)
+            .open(path.join("cgroup.procs"))?;
+
+        Ok(Self { path, procs_file })
+    }
+
+    /// File descriptor of cgroup.procs, writing "0" to it moves the writing process into the cgroup.
+    /// It is kept open so that it can be used from a pre-exec hook without allocating
+    pub fn procs_fd(&self) -> RawFd {
+        self.procs_file.as_raw_fd()
+    }
+
+    /// Maximum memory usage recorded for the cgroup, requires linux 5.19 or newer
+    pub fn peak_bytes(&self) -> Option<u64> {
+        std::fs::read_to_string(self.path.join("memory.peak"))
+            .ok()?
+            .trim()
+            .parse()
+            .ok()
+    }
+
+    pub fn events(&self) -> MemoryEvents {
+        let mut events = MemoryEvents::default();
+        if let Ok(content) = std::fs::read_to_string(self.path.join("memory.events")) {
+            for line in content.lines() {
+                let mut parts = line.split_whitespace();
+                match (parts.next(), parts.next().and_then(|v| v.parse().ok())) {
+                    (Some("oom"), Some(value)) => events.oom = value,
+                    (Some("oom_kill"), Some(value)) => events.oom_kill = value,
+                    _ => {}
+                }
+            }
+        }
+        events
+    }
+}
+
+impl Drop for MemoryCgroup {
+    fn drop(&mut self) {
+        if remove_dir(&self.path).is_err() {
+            println!("WARNING: Cannot remove the cgroup {}!", self.path.display());
+        }
+    }
+}
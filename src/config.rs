@@ -1,13 +1,17 @@
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::path::PathBuf;
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
 pub struct Tool {
     pub name: String,
     pub path: PathBuf,
+    /// Arguments template, see template::render_arguments for the syntax
     pub arguments: String,
+    /// Column of the tool in the tables, to merge variants of the same tool
+    pub group: Option<String>,
 
+    // Legacy input prefixes, superseded by conditionals in the arguments template
     #[serde(rename = "reads-arg-prefix")]
     pub reads_arg_prefix: Option<String>,
     #[serde(rename = "sequences-arg-prefix")]
@@ -15,6 +19,42 @@ pub struct Tool {
 
     #[serde(rename = "use-prefix-for-list")]
     pub use_prefix_for_list: Option<bool>,
+
+    /// Wall-clock timeout in seconds, overrides the benchmark one
+    pub timeout: Option<u64>,
+
+    /// Environment variables set for the tool, values are templates like the arguments
+    pub env: Option<BTreeMap<String, String>>,
+    /// Working directory of the tool, relative to the config directory
+    pub cwd: Option<String>,
+    /// Command prefixed to the tool, e.g. "numactl --cpunodebind=0"
+    pub launcher: Option<String>,
+
+    /// Arguments printing the version of the tool, e.g. "--version", recorded in the results
+    #[serde(rename = "version-arguments")]
+    pub version_arguments: Option<String>,
+
+    /// Files written by the tool, defaults to the fasta and gfa files starting with the output name
+    pub outputs: Option<Vec<OutputSpec>>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    Fasta,
+    Gfa1,
+    Gfa2,
+    /// Compressed output, cannot be canonicalized
+    Compressed,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct OutputSpec {
+    /// Glob pattern relative to the output directory, placeholders like <OUTPUT_FILE> are allowed
+    pub pattern: String,
+    pub format: OutputFormat,
+    /// The primary output is the graph required for completion and canonicalization
+    pub primary: Option<bool>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -28,6 +68,10 @@ pub struct Dataset {
     pub name: String,
     pub files: Option<Vec<PathBuf>>,
     pub lists: Option<Vec<PathBuf>>,
+    /// Directories whose files matching `pattern` are used as inputs
+    pub directories: Option<Vec<PathBuf>>,
+    pub pattern: Option<String>,
+    /// Tar archive, optionally compressed with gzip or zstd
     pub tar: Option<PathBuf>,
     pub limit: Option<usize>,
     pub query: Option<String>,
@@ -57,6 +101,77 @@ pub struct Benchmark {
     pub size_check_time: u64,
     #[serde(rename = "copy-output")]
     pub copy_output: Option<bool>,
+    pub repetitions: Option<usize>,
+    #[serde(rename = "warmup-runs")]
+    pub warmup_runs: Option<usize>,
+    /// Wall-clock timeout in seconds for each run
+    pub timeout: Option<u64>,
+    /// Hard memory limit enforced through a cgroup v2
+    #[serde(rename = "memory-limit-gb")]
+    pub memory_limit_gb: Option<f64>,
+    /// Tree-wide RSS at which the tool is killed, for machines without cgroups
+    #[serde(rename = "memory-kill-gb")]
+    pub memory_kill_gb: Option<f64>,
+    #[serde(rename = "memory-search")]
+    pub memory_search: Option<MemorySearch>,
+    /// Free space of the working dir filesystem required to start a run
+    #[serde(rename = "min-free-gb")]
+    pub min_free_gb: Option<f64>,
+    /// Disk usage of the temp and output dirs at which the tool is killed
+    #[serde(rename = "max-disk-gb")]
+    pub max_disk_gb: Option<f64>,
+    /// Page cache state of the inputs at the start of each run, left untouched if missing
+    #[serde(rename = "cache-state")]
+    pub cache_state: Option<CacheState>,
+    /// Method measuring the disk usage of the tools, defaults to statvfs for working dirs
+    /// on a dedicated filesystem and to inotify otherwise
+    #[serde(rename = "disk-usage-method")]
+    pub disk_usage_method: Option<DiskUsageMethod>,
+    /// Additional parameters taking each value of their list, exposed to the templates as <NAME>,
+    /// uppercase with dashes replaced by underscores. multiplicity and max-memory, spelled exactly so,
+    /// override the scalar options of the benchmark, the other placeholders cannot be swept
+    pub sweep: Option<BTreeMap<String, Vec<toml::Value>>>,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum MemoryLimitMethod {
+    /// Limit the address space of the tool with RLIMIT_AS
+    Rlimit,
+    /// Limit the memory of the tool with a cgroup v2, see cgroup-root
+    Cgroup,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheState {
+    /// Evict the inputs and the previous outputs from the page cache
+    Cold,
+    /// Read the inputs in the page cache
+    Warm,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum DiskUsageMethod {
+    /// Traverse the temp and output dirs at each sample
+    #[default]
+    Walk,
+    /// Track the sizes of the files from the inotify events
+    Inotify,
+    /// Difference of the used space of the filesystem from the start of the run
+    Statvfs,
+}
+
+#[derive(Deserialize, Serialize, Debug, Clone)]
+pub struct MemorySearch {
+    #[serde(rename = "lower-gb")]
+    pub lower_gb: f64,
+    #[serde(rename = "upper-gb")]
+    pub upper_gb: f64,
+    #[serde(rename = "precision-gb")]
+    pub precision_gb: Option<f64>,
+    pub method: Option<MemoryLimitMethod>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
@@ -69,6 +184,9 @@ pub struct LocalConfig {
     pub datasets: Vec<Dataset>,
     #[serde(rename = "working-dirs")]
     pub working_dirs: Vec<WorkingDir>,
+    /// Delegated cgroup v2 directory where the memory limited runs are placed
+    #[serde(rename = "cgroup-root")]
+    pub cgroup_root: Option<PathBuf>,
 }
 
 #[derive(Deserialize, Serialize, Debug, Clone)]
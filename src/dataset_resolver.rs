@@ -0,0 +1,457 @@
+use crate::config::Dataset;
+use crate::plan::resolve_path;
+use flate2::read::GzDecoder;
+use std::collections::{HashMap, HashSet};
+use std::ffi::OsStr;
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read};
+use std::path::{Path, PathBuf};
+
+/// Pattern of the files taken from the directories of a dataset without an explicit one
+const DEFAULT_DIRECTORY_PATTERN: &str = "*";
+
+/// A single input file of a dataset
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DatasetInput {
+    /// A file read from its location
+    File(PathBuf),
+    /// An entry of a tar archive, available only after its extraction
+    TarEntry { archive: PathBuf, entry: PathBuf },
+}
+
+impl DatasetInput {
+    pub fn file_name(&self) -> &OsStr {
+        match self {
+            DatasetInput::File(path) => path.file_name().unwrap(),
+            DatasetInput::TarEntry { entry, .. } => entry.file_name().unwrap(),
+        }
+    }
+
+    /// Location of the input, as shown to the user
+    pub fn source(&self) -> String {
+        match self {
+            DatasetInput::File(path) => path.display().to_string(),
+            DatasetInput::TarEntry { archive, entry } => {
+                format!("{}:{}", archive.display(), entry.display())
+            }
+        }
+    }
+
+    /// Path of the input once available locally: tar entries are extracted in `dest_dir`,
+    /// files are copied there only if `copy_files`
+    pub fn local_path(&self, dest_dir: &Path, copy_files: bool) -> PathBuf {
+        match self {
+            DatasetInput::File(path) if !copy_files => path.clone(),
+            _ => dest_dir.join(self.file_name()),
+        }
+    }
+}
+
+/// Resolves the input files of a dataset, relative paths (also inside the lists) are taken from the config dir
+pub struct DatasetResolver<'a> {
+    dataset: &'a Dataset,
+    base_dir: &'a Path,
+}
+
+/// Opens a tar archive, decompressing it according to its extension
+fn open_archive(archive: &Path) -> tar::Archive<Box<dyn Read>> {
+    let file = File::open(archive)
+        .unwrap_or_else(|err| panic!("Cannot open archive {}: {}", archive.display(), err));
+    let name = archive.to_string_lossy();
+
+    let reader: Box<dyn Read> = if name.ends_with(".tar.gz") || name.ends_with(".tgz") {
+        Box::new(GzDecoder::new(file))
+    } else if name.ends_with(".tar.zst") {
+        Box::new(zstd::Decoder::new(file).unwrap())
+    } else {
+        Box::new(file)
+    };
+    tar::Archive::new(reader)
+}
+
+/// Paths of the first `max_entries` regular files with an extension in the archive
+fn tar_entries(archive: &Path, max_entries: usize) -> Vec<PathBuf> {
+    if max_entries == 0 {
+        return vec![];
+    }
+    open_archive(archive)
+        .entries()
+        .unwrap()
+        .filter_map(|entry| {
+            let entry = entry.ok()?;
+            if !entry.header().entry_type().is_file() {
+                return None;
+            }
+            entry.path().ok().map(|path| path.into_owned())
+        })
+        .filter(|path| path.extension().is_some())
+        .take(max_entries)
+        .collect()
+}
+
+impl<'a> DatasetResolver<'a> {
+    pub fn new(dataset: &'a Dataset, base_dir: &'a Path) -> Self {
+        Self { dataset, base_dir }
+    }
+
+    fn list_files(&self, list: &Path) -> Vec<PathBuf> {
+        let list = resolve_path(self.base_dir, list);
+        BufReader::new(
+            File::open(&list)
+                .unwrap_or_else(|err| panic!("Cannot open list {}: {}", list.display(), err)),
+        )
+        .lines()
+        .map(|line| line.unwrap())
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| resolve_path(self.base_dir, Path::new(line.trim())))
+        .collect()
+    }
+
+    fn directory_files(&self, directory: &Path) -> Vec<PathBuf> {
+        let pattern = resolve_path(self.base_dir, directory).join(
+            self.dataset
+                .pattern
+                .as_deref()
+                .unwrap_or(DEFAULT_DIRECTORY_PATTERN),
+        );
+        let pattern = pattern.to_string_lossy();
+
+        let mut paths: Vec<PathBuf> = glob::glob(&pattern)
+            .unwrap_or_else(|err| panic!("Invalid dataset pattern {}: {}", pattern, err))
+            .filter_map(|path| path.ok())
+            .filter(|path| path.is_file())
+            .collect();
+        paths.sort();
+        paths
+    }
+
+    /// Inputs from the files, the lists, the directories and the tar archive, in this order,
+    /// truncated to the limit of the dataset
+    pub fn inputs(&self) -> Vec<DatasetInput> {
+        let limit = self.dataset.limit.unwrap_or(usize::MAX);
+
+        let mut inputs: Vec<_> = self
+            .dataset
+            .files
+            .iter()
+            .flatten()
+            .map(|file| resolve_path(self.base_dir, file))
+            .chain(
+                self.dataset
+                    .lists
+                    .iter()
+                    .flatten()
+                    .flat_map(|list| self.list_files(list)),
+            )
+            .chain(
+                self.dataset
+                    .directories
+                    .iter()
+                    .flatten()
+                    .flat_map(|directory| self.directory_files(directory)),
+            )
+            .take(limit)
+            .map(DatasetInput::File)
+            .collect();
+
+        if let Some(tar) = &self.dataset.tar {
+            let archive = resolve_path(self.base_dir, tar);
+            for entry in tar_entries(&archive, limit - inputs.len()) {
+                inputs.push(DatasetInput::TarEntry {
+                    archive: archive.clone(),
+                    entry,
+                });
+            }
+        }
+        inputs
+    }
+}
+
+/// Inputs of the datasets, each resolved (and its tar archive scanned) only the first time it is needed
+pub struct DatasetInputsCache<'a> {
+    base_dir: &'a Path,
+    inputs: HashMap<String, Vec<DatasetInput>>,
+}
+
+impl<'a> DatasetInputsCache<'a> {
+    pub fn new(base_dir: &'a Path) -> Self {
+        Self {
+            base_dir,
+            inputs: HashMap::new(),
+        }
+    }
+
+    pub fn inputs(&mut self, dataset: &Dataset) -> &[DatasetInput] {
+        let base_dir = self.base_dir;
+        self.inputs
+            .entry(dataset.name.clone())
+            .or_insert_with(|| DatasetResolver::new(dataset, base_dir).inputs())
+    }
+}
+
+/// Makes the inputs available locally, extracting the tar entries in `dest_dir` and copying there the
+/// files if `copy_files`. Returns the local paths, as given by `DatasetInput::local_path`.
+/// Panics if two of them have the same file name, as they would overwrite each other
+pub fn materialize_inputs(
+    inputs: &[DatasetInput],
+    dest_dir: &Path,
+    copy_files: bool,
+) -> Vec<PathBuf> {
+    let mut local_inputs: HashMap<PathBuf, &DatasetInput> = HashMap::new();
+    let mut duplicates = vec![];
+    for input in inputs {
+        let local_path = input.local_path(dest_dir, copy_files);
+        if local_path.starts_with(dest_dir) {
+            if let Some(other) = local_inputs.insert(local_path, input) {
+                duplicates.push(format!("{} and {}", other.source(), input.source()));
+            }
+        }
+    }
+    if !duplicates.is_empty() {
+        panic!(
+            "Inputs with the same file name would overwrite each other in {}: {}",
+            dest_dir.display(),
+            duplicates.join(", ")
+        );
+    }
+
+    let mut archives: HashMap<&Path, HashSet<&Path>> = HashMap::new();
+    for input in inputs {
+        if let DatasetInput::TarEntry { archive, entry } = input {
+            archives.entry(archive).or_default().insert(entry);
+        }
+    }
+
+    for (archive, entries) in archives {
+        println!("Unpacking tarball: {}", archive.display());
+        for entry in open_archive(archive).entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let path = entry.path().unwrap().into_owned();
+            if entries.contains(path.as_path()) {
+                entry
+                    .unpack(dest_dir.join(path.file_name().unwrap()))
+                    .unwrap();
+            }
+        }
+    }
+
+    inputs
+        .iter()
+        .map(|input| {
+            let local_path = input.local_path(dest_dir, copy_files);
+            if let DatasetInput::File(file) = input {
+                // Skip files already in dest dir
+                if *file != local_path {
+                    std::fs::copy(file, &local_path).expect(&format!(
+                        "Cannot copy file: {} to working dir {}",
+                        file.display(),
+                        local_path.display()
+                    ));
+                }
+            }
+            local_path
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use flate2::write::GzEncoder;
+    use std::fs::{create_dir_all, write};
+
+    fn setup(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("dataset-resolver-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    fn parse_dataset(config: &str) -> Dataset {
+        toml::from_str(&format!("name = \"test\"\n{}", config)).unwrap()
+    }
+
+    /// Writes a tar archive with the given regular files, a directory and a file without extension
+    fn write_tar<W: std::io::Write>(writer: W, files: &[&str]) -> W {
+        let mut builder = tar::Builder::new(writer);
+        let mut append = |path: &str, entry_type: tar::EntryType, data: &[u8]| {
+            let mut header = tar::Header::new_gnu();
+            header.set_entry_type(entry_type);
+            header.set_size(data.len() as u64);
+            header.set_mode(0o644);
+            header.set_cksum();
+            builder.append_data(&mut header, path, data).unwrap();
+        };
+        append("reads", tar::EntryType::Directory, b"");
+        append("reads/README", tar::EntryType::Regular, b"readme");
+        for file in files {
+            append(file, tar::EntryType::Regular, b">s\nACGT\n");
+        }
+        builder.into_inner().unwrap()
+    }
+
+    fn tar_entry(archive: &Path, entry: &str) -> DatasetInput {
+        DatasetInput::TarEntry {
+            archive: archive.to_path_buf(),
+            entry: PathBuf::from(entry),
+        }
+    }
+
+    #[test]
+    fn lists_relative_to_config_dir() {
+        let dir = setup("lists");
+        create_dir_all(dir.join("lists")).unwrap();
+        write(
+            dir.join("lists/inputs.list"),
+            "data/a.fa\n\n  data/b.fa  \n/absolute/c.fa\n",
+        )
+        .unwrap();
+
+        let dataset = parse_dataset("lists = [\"lists/inputs.list\"]");
+        assert_eq!(
+            DatasetResolver::new(&dataset, &dir).inputs(),
+            vec![
+                DatasetInput::File(dir.join("data/a.fa")),
+                DatasetInput::File(dir.join("data/b.fa")),
+                DatasetInput::File(PathBuf::from("/absolute/c.fa")),
+            ]
+        );
+    }
+
+    #[test]
+    fn directories_with_pattern() {
+        let dir = setup("directories");
+        for file in [
+            "one/b.fa",
+            "one/a.fa",
+            "one/c.fq",
+            "one/sub/d.fa",
+            "two/e.fa",
+        ] {
+            create_dir_all(dir.join(file).parent().unwrap()).unwrap();
+            write(dir.join(file), ">s\nACGT\n").unwrap();
+        }
+
+        let dataset = parse_dataset("directories = [\"one\", \"two\"]\npattern = \"*.fa\"");
+        assert_eq!(
+            DatasetResolver::new(&dataset, &dir).inputs(),
+            vec![
+                DatasetInput::File(dir.join("one/a.fa")),
+                DatasetInput::File(dir.join("one/b.fa")),
+                DatasetInput::File(dir.join("two/e.fa")),
+            ]
+        );
+
+        // Without a pattern every file is taken, but not the subdirectories
+        let dataset = parse_dataset("directories = [\"one\"]");
+        assert_eq!(
+            DatasetResolver::new(&dataset, &dir).inputs(),
+            vec![
+                DatasetInput::File(dir.join("one/a.fa")),
+                DatasetInput::File(dir.join("one/b.fa")),
+                DatasetInput::File(dir.join("one/c.fq")),
+            ]
+        );
+    }
+
+    #[test]
+    fn compressed_tar_entries() {
+        let dir = setup("tar");
+        let files = ["reads/a.fa", "reads/b.fa"];
+
+        let gz = write_tar(
+            GzEncoder::new(
+                File::create(dir.join("reads.tar.gz")).unwrap(),
+                Default::default(),
+            ),
+            &files,
+        );
+        gz.finish().unwrap();
+        let zst = write_tar(
+            zstd::Encoder::new(File::create(dir.join("reads.tar.zst")).unwrap(), 0).unwrap(),
+            &files,
+        );
+        zst.finish().unwrap();
+        write_tar(File::create(dir.join("reads.tar")).unwrap(), &files);
+
+        for archive in ["reads.tar.gz", "reads.tar.zst", "reads.tar"] {
+            let dataset = parse_dataset(&format!("tar = \"{}\"", archive));
+            let archive = dir.join(archive);
+            let inputs = DatasetResolver::new(&dataset, &dir).inputs();
+            assert_eq!(
+                inputs,
+                vec![
+                    tar_entry(&archive, "reads/a.fa"),
+                    tar_entry(&archive, "reads/b.fa"),
+                ]
+            );
+
+            let extract_dir = dir.join("extracted");
+            create_dir_all(&extract_dir).unwrap();
+            assert_eq!(
+                materialize_inputs(&inputs, &extract_dir, false),
+                vec![extract_dir.join("a.fa"), extract_dir.join("b.fa")]
+            );
+            assert_eq!(
+                std::fs::read_to_string(extract_dir.join("b.fa")).unwrap(),
+                ">s\nACGT\n"
+            );
+            std::fs::remove_dir_all(&extract_dir).unwrap();
+        }
+    }
+
+    #[test]
+    fn limit_across_sources() {
+        let dir = setup("limit");
+        write(dir.join("inputs.list"), "l1.fa\nl2.fa\n").unwrap();
+        write_tar(
+            File::create(dir.join("reads.tar")).unwrap(),
+            &["reads/t1.fa", "reads/t2.fa", "reads/t3.fa"],
+        );
+        let archive = dir.join("reads.tar");
+        let sources = "files = [\"f1.fa\"]\nlists = [\"inputs.list\"]\ntar = \"reads.tar\"";
+
+        let inputs = |limit: usize| {
+            let dataset = parse_dataset(&format!("{}\nlimit = {}", sources, limit));
+            DatasetResolver::new(&dataset, &dir).inputs()
+        };
+        assert_eq!(
+            inputs(5),
+            vec![
+                DatasetInput::File(dir.join("f1.fa")),
+                DatasetInput::File(dir.join("l1.fa")),
+                DatasetInput::File(dir.join("l2.fa")),
+                tar_entry(&archive, "reads/t1.fa"),
+                tar_entry(&archive, "reads/t2.fa"),
+            ]
+        );
+        assert_eq!(
+            inputs(2),
+            vec![
+                DatasetInput::File(dir.join("f1.fa")),
+                DatasetInput::File(dir.join("l1.fa")),
+            ]
+        );
+        assert_eq!(inputs(10).len(), 6);
+    }
+
+    #[test]
+    #[should_panic(expected = "Inputs with the same file name would overwrite each other")]
+    fn duplicate_file_names() {
+        let dir = setup("duplicates");
+        for file in ["one/a.fa", "two/a.fa"] {
+            create_dir_all(dir.join(file).parent().unwrap()).unwrap();
+            write(dir.join(file), ">s\nACGT\n").unwrap();
+        }
+        let dataset = parse_dataset("directories = [\"one\", \"two\"]");
+        let inputs = DatasetResolver::new(&dataset, &dir).inputs();
+
+        // Read in place they do not collide
+        let dest_dir = dir.join("dataset");
+        create_dir_all(&dest_dir).unwrap();
+        assert_eq!(materialize_inputs(&inputs, &dest_dir, false).len(), 2);
+
+        materialize_inputs(&inputs, &dest_dir, true);
+    }
+}
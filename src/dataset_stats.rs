@@ -1,19 +1,18 @@
 //
 
 use serde::{Deserialize, Serialize};
-use std::env::current_dir;
 use std::io::Write;
-use std::path::Path;
 use std::sync::atomic::AtomicU64;
 use std::sync::atomic::Ordering;
 
+use crate::dataset_resolver::{materialize_inputs, DatasetInput, DatasetResolver};
+use crate::dir_cleanup::create_dir_with_guard;
+use crate::plan::config_base_dir;
 use crate::{config::LocalConfig, parse_toml, DatasetStatsCli};
 use fastq::Record;
 use rayon::prelude::*;
 use std::cmp::max;
-use std::fs::File;
-use std::io::BufRead;
-use std::io::BufReader;
+use std::fs::{create_dir_all, File};
 use std::path::PathBuf;
 
 #[derive(Serialize, Deserialize, Debug)]
@@ -39,53 +38,42 @@ pub struct DatasetStats {
 }
 
 pub fn compute_dataset_stats(args: DatasetStatsCli) {
-    let base_dir = if args.env_config.is_absolute() {
-        args.env_config.parent().unwrap().to_path_buf()
-    } else {
-        current_dir()
-            .unwrap()
-            .join(&args.env_config)
-            .parent()
-            .unwrap()
-            .to_path_buf()
-    };
-
+    let base_dir = config_base_dir(&args.env_config);
     let local_env = parse_toml::<LocalConfig>(args.env_config);
     let dataset = local_env
         .datasets
-        .into_iter()
+        .iter()
         .filter(|d| d.name == args.dataset)
         .next()
         .unwrap();
 
-    let mut input_files: Vec<_> = dataset
-        .files
-        .as_ref()
-        .unwrap_or(&Vec::new())
+    let inputs = DatasetResolver::new(dataset, &base_dir).inputs();
+    let has_tar_entries = inputs
         .iter()
-        .map(|x| {
-            let path = if x.is_absolute() {
-                x.clone()
-            } else {
-                base_dir.join(x)
-            };
-            path
-        })
-        .collect();
-
-    if let Some(lists) = &dataset.lists {
-        for list in lists {
-            let list = if list.is_absolute() {
-                list.clone()
-            } else {
-                base_dir.join(list)
-            };
+        .any(|input| matches!(input, DatasetInput::TarEntry { .. }));
 
-            for line in BufReader::new(File::open(list).unwrap()).lines() {
-                input_files.push(PathBuf::from(line.unwrap()));
+    // Without an explicit directory the entries are extracted in a temporary one, removed at the end
+    let (extract_dir, _extract_guard) = match args.extract_dir {
+        Some(extract_dir) => {
+            if has_tar_entries {
+                create_dir_all(&extract_dir).unwrap();
             }
+            (extract_dir, None)
+        }
+        None if has_tar_entries => {
+            let dir = std::env::temp_dir().join(format!(
+                "dataset-stats-{}-{}",
+                dataset.name,
+                std::process::id()
+            ));
+            let guard = create_dir_with_guard(&dir)
+                .unwrap_or_else(|| panic!("Cannot create extraction dir: {}", dir.display()));
+            (dir, Some(guard))
         }
-    }
+        // Only the tar entries are extracted, the other inputs are read in place
+        None => (PathBuf::new(), None),
+    };
+    let input_files = materialize_inputs(&inputs, &extract_dir, false);
 
     let files_count = AtomicU64::new(0);
     let files_size = AtomicU64::new(0);
@@ -112,30 +100,6 @@ pub fn compute_dataset_stats(args: DatasetStatsCli) {
         .map(|_| AtomicU64::new(0))
         .collect::<Vec<_>>();
 
-    if let Some(tarball) = &dataset.tar {
-        println!("Unpacking tarball: {}", tarball.display());
-        for entry in tar::Archive::new(File::open(tarball).unwrap())
-            .entries()
-            .unwrap()
-            .filter(|d| d.is_ok() && d.as_ref().unwrap().path().unwrap().extension().is_some())
-            .take(
-                dataset
-                    .limit
-                    .map(|limit| limit - input_files.len())
-                    .unwrap_or(usize::MAX),
-            )
-        {
-            let mut entry = entry.unwrap();
-            let tmp_path = entry.path().unwrap();
-            let file_name = tmp_path.file_name().unwrap();
-
-            let dest_file = PathBuf::from("working-dirs/hdd").join(file_name);
-
-            entry.unpack(&dest_file).unwrap();
-            input_files.push(dest_file);
-        }
-    }
-
     let logging_steps = (input_files.len() / 20) + 1;
 
     println!("Starting processing...");
@@ -0,0 +1,463 @@
+use crate::config::DiskUsageMethod;
+use serde::{Deserialize, Serialize};
+use std::collections::{HashMap, HashSet};
+use std::ffi::CString;
+use std::io;
+use std::mem::MaybeUninit;
+use std::os::unix::ffi::OsStrExt;
+use std::path::{Path, PathBuf};
+use std::time::Instant;
+use walkdir::WalkDir;
+
+/// Bytes used by the tool, per directory when the method can tell them apart
+#[derive(Debug, Clone, Copy)]
+pub struct DiskUsage {
+    pub temp_dir_bytes: Option<u64>,
+    pub output_dir_bytes: Option<u64>,
+    pub total_bytes: u64,
+}
+
+/// Cost of the disk usage measurements, to check that they do not perturb the tool
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DiskSamplerStats {
+    pub method: DiskUsageMethod,
+    pub samples: u64,
+    /// Wall-clock time spent measuring the disk usage
+    pub sampling_time_secs: f64,
+    pub max_sample_time_secs: f64,
+    /// Files and directories whose metadata was read
+    pub stat_calls: u64,
+    pub inotify_events: u64,
+    /// Full traversals after an overflow of the inotify queue
+    pub rescans: u64,
+    /// Cpu time of the whole monitoring thread, also sampling the processes of the tool
+    pub monitor_cpu_time_secs: f64,
+}
+
+/// Writes are not watched, as IN_MODIFY would report each of them: the files are read when they are
+/// closed after writing, and at every sample while open
+const WATCH_MASK: u32 = libc::IN_CREATE
+    | libc::IN_OPEN
+    | libc::IN_CLOSE_WRITE
+    | libc::IN_CLOSE_NOWRITE
+    | libc::IN_DELETE
+    | libc::IN_MOVED_FROM
+    | libc::IN_MOVED_TO
+    | libc::IN_DELETE_SELF
+    | libc::IN_ONLYDIR;
+
+/// Sizes of the files and directories under the temp and output dirs, updated from the inotify events
+struct InotifyTracker {
+    fd: libc::c_int,
+    roots: [PathBuf; 2],
+    watches: HashMap<libc::c_int, PathBuf>,
+    /// Watch descriptor of each watched directory
+    watched_dirs: HashMap<PathBuf, libc::c_int>,
+    /// Root index and size of each file and directory
+    files: HashMap<PathBuf, (usize, u64)>,
+    /// Number of open descriptors of the files that may be growing
+    open_files: HashMap<PathBuf, u32>,
+    totals: [u64; 2],
+}
+
+impl InotifyTracker {
+    fn new(roots: [PathBuf; 2]) -> io::Result<Self> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(Self {
+            fd,
+            roots,
+            watches: HashMap::new(),
+            watched_dirs: HashMap::new(),
+            files: HashMap::new(),
+            open_files: HashMap::new(),
+            totals: [0; 2],
+        })
+    }
+
+    fn root_of(&self, path: &Path) -> Option<usize> {
+        self.roots.iter().position(|root| path.starts_with(root))
+    }
+
+    fn is_watched(&self, dir: &Path) -> bool {
+        self.watched_dirs.contains_key(dir)
+    }
+
+    fn set_size(&mut self, path: &Path, size: Option<u64>) {
+        let root = match self.root_of(path) {
+            Some(root) => root,
+            None => return,
+        };
+        if let Some((_, old_size)) = self.files.remove(path) {
+            self.totals[root] -= old_size;
+        }
+        if let Some(size) = size {
+            self.files.insert(path.to_path_buf(), (root, size));
+            self.totals[root] += size;
+        }
+    }
+
+    /// Forgets the files under `dir`, returning the open ones relative to it
+    fn remove_subtree(&mut self, dir: &Path) -> Vec<(PathBuf, u32)> {
+        let removed: Vec<_> = self
+            .files
+            .keys()
+            .filter(|path| path.starts_with(dir))
+            .cloned()
+            .collect();
+        for path in removed {
+            self.set_size(&path, None);
+        }
+        let open_files = self
+            .open_files
+            .iter()
+            .filter_map(|(path, &count)| {
+                let relative = path.strip_prefix(dir).ok()?;
+                Some((relative.to_path_buf(), count))
+            })
+            .collect();
+        self.open_files.retain(|path, _| !path.starts_with(dir));
+        open_files
+    }
+
+    /// Watches a directory and all its subdirectories, and reads the sizes of the files already there.
+    /// The watch is added before the traversal, so that no file created meanwhile is missed.
+    /// The files found while the tool runs may have been opened before the watch, so they are
+    /// considered open until they are closed
+    fn add_tree(&mut self, dir: &Path, stats: &mut DiskSamplerStats, tool_running: bool) {
+        for entry in WalkDir::new(dir).into_iter().filter_map(|e| e.ok()) {
+            stats.stat_calls += 1;
+            let metadata = match entry.metadata() {
+                Ok(metadata) => metadata,
+                Err(_) => continue,
+            };
+            if metadata.is_dir() {
+                if !self.is_watched(entry.path()) {
+                    let path = CString::new(entry.path().as_os_str().as_bytes()).unwrap();
+                    let wd = unsafe { libc::inotify_add_watch(self.fd, path.as_ptr(), WATCH_MASK) };
+                    if wd >= 0 {
+                        // A directory moved within the roots keeps its watch
+                        if let Some(old_path) = self.watches.insert(wd, entry.path().to_path_buf())
+                        {
+                            self.watched_dirs.remove(&old_path);
+                        }
+                        self.watched_dirs.insert(entry.path().to_path_buf(), wd);
+                    }
+                }
+            } else if tool_running {
+                self.open_files
+                    .entry(entry.path().to_path_buf())
+                    .or_insert(1);
+            }
+            self.set_size(entry.path(), Some(metadata.len()));
+        }
+    }
+
+    fn rescan(&mut self, stats: &mut DiskSamplerStats, tool_running: bool) {
+        self.files.clear();
+        self.open_files.clear();
+        self.totals = [0; 2];
+        for root in self.roots.clone() {
+            self.add_tree(&root, stats, tool_running);
+        }
+    }
+
+    /// Applies the pending events, then reads the metadata of the open files and of the changed ones
+    fn update(&mut self, stats: &mut DiskSamplerStats) {
+        let mut changed = HashSet::new();
+        // Open files being moved, relative to the moved path, by the cookie of the move
+        let mut moved_open_files = HashMap::new();
+        // Directories moved within the roots, whose closed files are known
+        let mut moved_dirs = HashSet::new();
+        let mut overflow = false;
+        let mut buffer = vec![0u8; 64 * 1024];
+
+        loop {
+            let read = unsafe {
+                libc::read(
+                    self.fd,
+                    buffer.as_mut_ptr() as *mut libc::c_void,
+                    buffer.len(),
+                )
+            };
+            if read <= 0 {
+                break;
+            }
+
+            let mut offset = 0;
+            while offset < read as usize {
+                let event = unsafe {
+                    std::ptr::read_unaligned(buffer[offset..].as_ptr() as *const libc::inotify_event)
+                };
+                let name_start = offset + std::mem::size_of::<libc::inotify_event>();
+                let name = &buffer[name_start..name_start + event.len as usize];
+                let name = &name[..name.iter().position(|&c| c == 0).unwrap_or(name.len())];
+                offset = name_start + event.len as usize;
+                stats.inotify_events += 1;
+
+                if event.mask & libc::IN_Q_OVERFLOW != 0 {
+                    overflow = true;
+                    continue;
+                }
+                if event.mask & libc::IN_IGNORED != 0 {
+                    if let Some(dir) = self.watches.remove(&event.wd) {
+                        self.watched_dirs.remove(&dir);
+                    }
+                    continue;
+                }
+                let dir = match self.watches.get(&event.wd) {
+                    Some(dir) => dir,
+                    None => continue,
+                };
+                if name.is_empty() {
+                    continue;
+                }
+                let path = dir.join(std::ffi::OsStr::from_bytes(name));
+                let is_dir = event.mask & libc::IN_ISDIR != 0;
+
+                if event.mask & (libc::IN_DELETE | libc::IN_MOVED_FROM) != 0 {
+                    let open_files = if is_dir {
+                        self.remove_subtree(&path)
+                    } else {
+                        self.set_size(&path, None);
+                        self.open_files
+                            .remove(&path)
+                            .map(|count| (PathBuf::new(), count))
+                            .into_iter()
+                            .collect()
+                    };
+                    if event.mask & libc::IN_MOVED_FROM != 0 {
+                        moved_open_files.insert(event.cookie, open_files);
+                    }
+                    changed.remove(&path);
+                } else if event.mask & (libc::IN_CREATE | libc::IN_MOVED_TO) != 0 {
+                    if event.mask & libc::IN_MOVED_TO != 0 {
+                        if let Some(open_files) = moved_open_files.remove(&event.cookie) {
+                            for (relative, count) in open_files {
+                                let moved_path = if relative.as_os_str().is_empty() {
+                                    path.clone()
+                                } else {
+                                    path.join(relative)
+                                };
+                                self.open_files.insert(moved_path, count);
+                            }
+                            moved_dirs.insert(path.clone());
+                        }
+                    }
+                    changed.insert(path);
+                } else if is_dir {
+                    // Directories opened for listing
+                    continue;
+                } else if event.mask & libc::IN_OPEN != 0 {
+                    *self.open_files.entry(path).or_insert(0) += 1;
+                } else {
+                    if let Some(count) = self.open_files.get_mut(&path) {
+                        *count -= 1;
+                        if *count == 0 {
+                            self.open_files.remove(&path);
+                        }
+                    }
+                    if event.mask & libc::IN_CLOSE_WRITE != 0 {
+                        changed.insert(path);
+                    }
+                }
+            }
+        }
+
+        // The roots can be deleted and recreated by the tool
+        for root in self.roots.clone() {
+            if !self.is_watched(&root) && root.exists() {
+                self.remove_subtree(&root);
+                self.add_tree(&root, stats, true);
+            }
+        }
+
+        if overflow {
+            stats.rescans += 1;
+            self.rescan(stats, true);
+            return;
+        }
+
+        changed.extend(self.open_files.keys().cloned());
+        for path in changed {
+            stats.stat_calls += 1;
+            match std::fs::symlink_metadata(&path) {
+                Ok(metadata) if metadata.is_dir() && !self.is_watched(&path) => {
+                    self.add_tree(&path, stats, !moved_dirs.contains(&path))
+                }
+                Ok(metadata) => self.set_size(&path, Some(metadata.len())),
+                Err(_) => {
+                    self.open_files.remove(&path);
+                    self.set_size(&path, None)
+                }
+            }
+        }
+    }
+}
+
+impl Drop for InotifyTracker {
+    fn drop(&mut self) {
+        unsafe {
+            libc::close(self.fd);
+        }
+    }
+}
+
+fn statvfs(path: &Path) -> io::Result<libc::statvfs> {
+    let path = CString::new(path.as_os_str().as_bytes()).unwrap();
+    unsafe {
+        let mut stat: libc::statvfs = MaybeUninit::zeroed().assume_init();
+        if libc::statvfs(path.as_ptr(), &mut stat) < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(stat)
+    }
+}
+
+/// Used bytes of the filesystem containing `path`
+fn filesystem_used_bytes(path: &Path) -> io::Result<u64> {
+    let stat = statvfs(path)?;
+    Ok((stat.f_blocks - stat.f_bfree) as u64 * stat.f_frsize as u64)
+}
+
+/// Bytes available to unprivileged users in the filesystem containing `path`
+pub fn filesystem_free_bytes(path: &Path) -> io::Result<u64> {
+    let stat = statvfs(path)?;
+    Ok(stat.f_bavail as u64 * stat.f_frsize as u64)
+}
+
+/// Checks that the filesystem containing `path` has at least `min_free_gb` of free space
+pub fn has_free_space(path: &Path, min_free_gb: f64) -> bool {
+    let free_gb = filesystem_free_bytes(path)
+        .unwrap_or_else(|err| panic!("Cannot read the free space of {}: {}", path.display(), err))
+        as f64
+        / (1024.0 * 1024.0 * 1024.0);
+    if free_gb < min_free_gb {
+        println!(
+            "Only {:.2}GB free in {}, at least {:.2}GB required",
+            free_gb,
+            path.display(),
+            min_free_gb
+        );
+    }
+    free_gb >= min_free_gb
+}
+
+fn walk_dir_size(path: &Path, stats: &mut DiskSamplerStats) -> u64 {
+    let mut dir_size = 0;
+    for entry in WalkDir::new(path).into_iter().filter_map(|e| e.ok()) {
+        stats.stat_calls += 1;
+        dir_size += entry.metadata().map(|m| m.len()).unwrap_or(0)
+    }
+    dir_size
+}
+
+enum Backend {
+    Walk,
+    Inotify(InotifyTracker),
+    Statvfs { initial_used_bytes: u64 },
+}
+
+pub struct DiskSampler {
+    temp_dir: PathBuf,
+    output_dir: PathBuf,
+    backend: Backend,
+    stats: DiskSamplerStats,
+}
+
+impl DiskSampler {
+    /// Must be created before the tool starts, falls back to the traversal of the directories
+    /// if the method is not available
+    pub fn new(method: DiskUsageMethod, temp_dir: &Path, output_dir: &Path) -> Self {
+        let mut stats = DiskSamplerStats::default();
+
+        let backend = match method {
+            DiskUsageMethod::Walk => Backend::Walk,
+            DiskUsageMethod::Inotify => {
+                match InotifyTracker::new([temp_dir.to_path_buf(), output_dir.to_path_buf()]) {
+                    Ok(mut tracker) => {
+                        tracker.rescan(&mut stats, false);
+                        Backend::Inotify(tracker)
+                    }
+                    Err(err) => {
+                        println!("Cannot use inotify to measure the disk usage: {}", err);
+                        Backend::Walk
+                    }
+                }
+            }
+            DiskUsageMethod::Statvfs => match filesystem_used_bytes(temp_dir) {
+                Ok(initial_used_bytes) => Backend::Statvfs { initial_used_bytes },
+                Err(err) => {
+                    println!("Cannot use statvfs to measure the disk usage: {}", err);
+                    Backend::Walk
+                }
+            },
+        };
+
+        stats.method = match backend {
+            Backend::Walk => DiskUsageMethod::Walk,
+            Backend::Inotify(_) => DiskUsageMethod::Inotify,
+            Backend::Statvfs { .. } => DiskUsageMethod::Statvfs,
+        };
+
+        Self {
+            temp_dir: temp_dir.to_path_buf(),
+            output_dir: output_dir.to_path_buf(),
+            backend,
+            stats,
+        }
+    }
+
+    pub fn sample(&mut self) -> DiskUsage {
+        let start = Instant::now();
+        let stats = &mut self.stats;
+
+        let usage = match &mut self.backend {
+            Backend::Walk => {
+                let temp_dir_bytes = walk_dir_size(&self.temp_dir, stats);
+                let output_dir_bytes = walk_dir_size(&self.output_dir, stats);
+                DiskUsage {
+                    temp_dir_bytes: Some(temp_dir_bytes),
+                    output_dir_bytes: Some(output_dir_bytes),
+                    total_bytes: temp_dir_bytes + output_dir_bytes,
+                }
+            }
+            Backend::Inotify(tracker) => {
+                tracker.update(stats);
+                DiskUsage {
+                    temp_dir_bytes: Some(tracker.totals[0]),
+                    output_dir_bytes: Some(tracker.totals[1]),
+                    total_bytes: tracker.totals[0] + tracker.totals[1],
+                }
+            }
+            Backend::Statvfs { initial_used_bytes } => {
+                stats.stat_calls += 1;
+                DiskUsage {
+                    temp_dir_bytes: None,
+                    output_dir_bytes: None,
+                    total_bytes: filesystem_used_bytes(&self.temp_dir)
+                        .unwrap_or(0)
+                        .saturating_sub(*initial_used_bytes),
+                }
+            }
+        };
+
+        let elapsed = start.elapsed().as_secs_f64();
+        self.stats.samples += 1;
+        self.stats.sampling_time_secs += elapsed;
+        self.stats.max_sample_time_secs = self.stats.max_sample_time_secs.max(elapsed);
+        usage
+    }
+
+    /// Returns the statistics of the sampler, must be called by the monitoring thread
+    pub fn finish(mut self) -> DiskSamplerStats {
+        self.stats.monitor_cpu_time_secs = unsafe {
+            let mut time: libc::timespec = MaybeUninit::zeroed().assume_init();
+            libc::clock_gettime(libc::CLOCK_THREAD_CPUTIME_ID, &mut time);
+            time.tv_sec as f64 + time.tv_nsec as f64 / 1e9
+        };
+        self.stats
+    }
+}
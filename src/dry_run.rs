@@ -0,0 +1,169 @@
+use crate::config::{Benchmark, Dataset, LocalConfig};
+use crate::dataset_resolver::DatasetInputsCache;
+use crate::disk_usage::filesystem_free_bytes;
+use crate::plan::{resolve_working_path, results_file_name, PlannedRun};
+use crate::rerun::RerunPolicy;
+use crate::runner::Runner;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// What a single run of the benchmark would do
+#[derive(Serialize)]
+pub struct PlanEntry {
+    pub index: usize,
+    pub dataset: String,
+    pub working_dir: String,
+    pub k: usize,
+    pub threads: usize,
+    pub tool: String,
+    /// Values of the swept parameters
+    pub sweep: BTreeMap<String, String>,
+    pub command_line: String,
+    pub input_files: Vec<String>,
+    pub working_path: String,
+    pub temp_dir: String,
+    pub output_dir: String,
+    pub results_file: String,
+    /// Reason why the run would be skipped, if any
+    pub skip: Option<String>,
+}
+
+/// Input files as seen by the tool, after the optional copy in the working dir
+fn planned_input_files(
+    dataset_inputs: &mut DatasetInputsCache,
+    dataset: &Dataset,
+    benchmark: &Benchmark,
+    dataset_dir: &Path,
+) -> Vec<PathBuf> {
+    dataset_inputs
+        .inputs(dataset)
+        .iter()
+        .map(|input| input.local_path(dataset_dir, benchmark.copy_dataset))
+        .collect()
+}
+
+pub fn plan_entries(
+    runs: &[PlannedRun],
+    benchmark: &Benchmark,
+    local_env: &LocalConfig,
+    base_dir: &Path,
+    results_dir: &Path,
+    logs_dir: &Path,
+    memory_search: bool,
+    rerun_policy: &RerunPolicy,
+) -> Vec<PlanEntry> {
+    let mut dataset_inputs = DatasetInputsCache::new(base_dir);
+    runs.iter()
+        .map(|run| {
+            let working_path = resolve_working_path(base_dir, run.working_dir);
+            let input_files = planned_input_files(
+                &mut dataset_inputs,
+                run.dataset,
+                benchmark,
+                &working_path.join("dataset"),
+            );
+            let parameters = run.parameters(
+                benchmark,
+                local_env,
+                &working_path,
+                logs_dir.join(format!("{}.log", run.base_name)),
+                None,
+            );
+            let command = Runner::build_command(base_dir, run.tool, &input_files, &parameters);
+
+            let results_file = results_dir.join(results_file_name(&run.base_name, memory_search));
+            let skip = if results_file.exists()
+                && rerun_policy
+                    .rerun_reason(&results_file, &command.tool_path)
+                    .is_none()
+            {
+                Some("results file exists".to_string())
+            } else if run.dataset.tar.is_some() && !benchmark.copy_dataset {
+                Some("tar dataset without copy-dataset".to_string())
+            } else if let Some(min_free_gb) = benchmark.min_free_gb {
+                // The working dir is created by the run, its closest existing ancestor is checked
+                let free_gb = working_path
+                    .ancestors()
+                    .find_map(|dir| filesystem_free_bytes(dir).ok())
+                    .unwrap_or(u64::MAX) as f64
+                    / (1024.0 * 1024.0 * 1024.0);
+                (free_gb < min_free_gb).then(|| {
+                    format!(
+                        "{:.2}GB free, {:.2}GB required by min-free-gb",
+                        free_gb, min_free_gb
+                    )
+                })
+            } else {
+                None
+            };
+
+            PlanEntry {
+                index: run.index,
+                dataset: run.dataset.name.clone(),
+                sweep: run.sweep.clone(),
+                working_dir: run.working_dir.name.clone(),
+                k: run.k,
+                threads: run.threads,
+                tool: run.tool.name.clone(),
+                command_line: command.full_command_line(),
+                input_files: input_files
+                    .iter()
+                    .map(|f| f.to_string_lossy().into_owned())
+                    .collect(),
+                working_path: working_path.to_string_lossy().into_owned(),
+                temp_dir: parameters.temp_dir.clone(),
+                output_dir: run.out_dir(&working_path).to_string_lossy().into_owned(),
+                results_file: results_file.to_string_lossy().into_owned(),
+                skip,
+            }
+        })
+        .collect()
+}
+
+pub fn print_plan_table(entries: &[PlanEntry]) {
+    println!(
+        "{:>5}  {:<20} {:<10} {:>4} {:>7}  {:<24} {:<24} {}",
+        "run", "dataset", "workdir", "k", "threads", "tool", "sweep", "status"
+    );
+    for entry in entries {
+        let sweep = entry
+            .sweep
+            .iter()
+            .map(|(name, value)| format!("{}={}", name, value))
+            .collect::<Vec<_>>()
+            .join(",");
+        println!(
+            "{:>5}  {:<20} {:<10} {:>4} {:>7}  {:<24} {:<24} {}",
+            entry.index,
+            entry.dataset,
+            entry.working_dir,
+            entry.k,
+            entry.threads,
+            entry.tool,
+            if sweep.is_empty() { "-" } else { &sweep },
+            entry
+                .skip
+                .as_ref()
+                .map(|reason| format!("skip ({})", reason))
+                .unwrap_or("run".to_string())
+        );
+        println!("         command: {}", entry.command_line);
+        println!(
+            "         inputs ({}): {}",
+            entry.input_files.len(),
+            entry.input_files.join(" ")
+        );
+        println!("         working dir: {}", entry.working_path);
+        println!("         temp dir: {}", entry.temp_dir);
+        println!("         output dir: {}", entry.output_dir);
+        println!("         results: {}", entry.results_file);
+    }
+
+    let skipped = entries.iter().filter(|e| e.skip.is_some()).count();
+    println!(
+        "{} runs planned, {} would be skipped",
+        entries.len() - skipped,
+        skipped
+    );
+}
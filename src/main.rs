@@ -1,34 +1,59 @@
 #![allow(warnings)]
+mod cgroup;
 pub mod compare_eulertigs;
 pub mod config;
+mod dataset_resolver;
 mod dataset_stats;
 mod dir_cleanup;
+mod disk_usage;
+mod dry_run;
+mod memory_search;
+mod outputs;
+mod page_cache;
+mod plan;
+mod process;
+mod provenance;
 pub mod randomize_fasta;
+mod rerun;
+mod results_schema;
 pub mod runner;
 mod stats;
+mod submit;
+mod summary;
 mod table_maker;
+mod template;
 
-use crate::config::{BenchmarksConfig, Config, LocalConfig, Tools};
+use crate::config::MemoryLimitMethod;
+use crate::dataset_resolver::{materialize_inputs, DatasetInputsCache};
 use crate::dir_cleanup::{create_dir_with_guard, remove_dirs_on_panic};
-use crate::runner::{Parameters, RunResults, Runner};
+use crate::disk_usage::has_free_space;
+use crate::dry_run::{plan_entries, print_plan_table};
+use crate::memory_search::search_min_memory;
+use crate::plan::{
+    find_benchmark, load_config, resolve_working_path, results_file_name, RunMatrix,
+};
+use crate::process::{interrupt, is_interrupted};
+use crate::rerun::{archive_run_files, parse_date, RerunPolicy};
+use crate::results_schema::{migrate_results, MigrateResultsCli};
+use crate::runner::{RunOutcome, Runner};
+use crate::submit::{submit, SubmitCli};
+use crate::summary::{median_run_index, RunsSummary};
 use crate::table_maker::{make_table, TableMakerCli};
-use cgroups_rs::cgroup_builder::CgroupBuilder;
-use cgroups_rs::Cgroup;
+#[cfg(feature = "cpu-limit")]
+use cgroups_rs::{cgroup_builder::CgroupBuilder, Cgroup};
 use compare_eulertigs::compare_eulertigs;
 use dataset_stats::compute_dataset_stats;
 use randomize_fasta::randomize_fasta;
 use serde::de::DeserializeOwned;
-use serde::Deserialize;
-use std::env::current_dir;
-use std::ffi::CString;
+use serde::Serialize;
 use std::fs::{create_dir, create_dir_all, read_dir, remove_dir_all, File};
-use std::io::{BufRead, BufReader, Read, Write};
+use std::io::{Read, Write};
+#[cfg(feature = "cpu-limit")]
 use std::os::unix::fs::PermissionsExt;
 use std::panic;
-use std::panic::resume_unwind;
 use std::path::{Path, PathBuf};
 use std::process::exit;
-use std::time::Duration;
+use std::time::SystemTime;
 use structopt::*;
 
 #[derive(StructOpt)]
@@ -36,7 +61,9 @@ enum ExtendedCli {
     #[cfg(feature = "cpu-limit")]
     Start(StartOpt),
     Bench(Cli),
+    Submit(SubmitCli),
     MakeTable(TableMakerCli),
+    MigrateResults(MigrateResultsCli),
     Canonicalize(CanonicalizeCli),
     DatasetStats(DatasetStatsCli),
     CompareEulertigs(CanonicalEulertigsCompareCli),
@@ -53,6 +80,11 @@ pub struct DatasetStatsCli {
 
     #[structopt(short, long, default_value = "config/local.toml")]
     env_config: PathBuf,
+
+    /// Directory where the tar entries of the dataset are extracted, defaults to a temporary directory
+    /// removed at the end
+    #[structopt(long)]
+    extract_dir: Option<PathBuf>,
 }
 
 #[derive(StructOpt)]
@@ -101,37 +133,64 @@ struct Cli {
     exclude: Option<String>,
     #[structopt(long)]
     threads: Option<String>,
+    /// Search the minimum memory budget at which each run completes, instead of benchmarking
+    #[structopt(long)]
+    memory_search: bool,
+    /// Execute only the run with this index in the expanded run matrix, used by submitted jobs.
+    /// The run uses a subdirectory of the working dir named after it
+    #[structopt(long)]
+    run_index: Option<usize>,
+    /// Rerun all the combinations, archiving the existing results
+    #[structopt(long)]
+    force: bool,
+    /// Rerun the combinations whose recorded run did not complete
+    #[structopt(long)]
+    rerun_failed: bool,
+    /// Rerun the combinations with results older than this date, as YYYY-MM-DD or RFC 3339
+    #[structopt(long, parse(try_from_str = parse_date))]
+    rerun_older_than: Option<SystemTime>,
+    /// Rerun the combinations whose tool binary changed since the recorded run
+    #[structopt(long)]
+    rerun_if_tool_changed: bool,
+    /// Print the planned runs without executing them
+    #[structopt(long)]
+    dry_run: bool,
+    /// Format of the dry run plan
+    #[structopt(long, default_value = "table", possible_values = &["table", "json"])]
+    plan_format: String,
 }
 
-fn filter_options<T>(
-    name: &str,
-    options: Vec<T>,
-    mapper: fn(&T) -> &String,
-    include: &Vec<String>,
-    exclude: &Vec<String>,
-) -> Vec<T> {
-    let include_matches = options.iter().any(|x| include.contains(mapper(x)));
-    let exclude_matches = options.iter().any(|x| exclude.contains(mapper(x)));
-
-    if include_matches && exclude_matches {
-        println!(
-            "Warning: both includes and excludes match on parameter: {}",
-            name
+fn prepare_run_dirs(temp_dir: &Path, out_dir: &Path) {
+    if temp_dir.exists() && temp_dir.read_dir().unwrap().next().is_some() {
+        panic!(
+            "Temporary directory {} not empty!, aborting (file: {})",
+            temp_dir.display(),
+            temp_dir
+                .read_dir()
+                .unwrap()
+                .next()
+                .unwrap()
+                .unwrap()
+                .file_name()
+                .into_string()
+                .unwrap()
+        );
+    }
+    if out_dir.exists() && out_dir.read_dir().unwrap().next().is_some() {
+        panic!(
+            "Output directory {} not empty!, aborting",
+            out_dir.display()
         );
     }
+    create_dir_all(&temp_dir);
+    create_dir_all(&out_dir);
+}
 
-    options
-        .into_iter()
-        .filter(|x| {
-            if include_matches {
-                include.contains(mapper(x))
-            } else if exclude_matches {
-                !exclude.contains(mapper(x))
-            } else {
-                true
-            }
-        })
-        .collect()
+fn write_json(file: &Path, value: &impl Serialize) {
+    File::create(file)
+        .unwrap()
+        .write_all(serde_json::to_string_pretty(value).unwrap().as_bytes())
+        .unwrap();
 }
 
 pub(crate) fn parse_toml<T: DeserializeOwned>(file: PathBuf) -> T {
@@ -146,8 +205,14 @@ pub(crate) fn parse_toml<T: DeserializeOwned>(file: PathBuf) -> T {
 fn main() {
     let args: ExtendedCli = ExtendedCli::from_args();
 
-    ctrlc::set_handler(|| {
-        panic!("Ctrl+C pressed, aborting!");
+    // Benchmarks stop the running tool and record the interrupted run
+    let graceful_interrupt = matches!(args, ExtendedCli::Bench(_));
+    ctrlc::set_handler(move || {
+        if graceful_interrupt {
+            interrupt();
+        } else {
+            panic!("Ctrl+C pressed, aborting!");
+        }
     })
     .unwrap();
 
@@ -200,152 +265,73 @@ fn main() {
             }
         }
         ExtendedCli::Bench(args) => {
-            let mut all_settings: Config = Config {
-                tools: vec![],
-                datasets: vec![],
-                benchmarks: vec![],
-                working_dirs: vec![],
-            };
-
-            let base_dir = if args.env_config.is_absolute() {
-                args.env_config.parent().unwrap().to_path_buf()
-            } else {
-                current_dir()
-                    .unwrap()
-                    .join(&args.env_config)
-                    .parent()
-                    .unwrap()
-                    .to_path_buf()
-            };
-
-            all_settings
-                .tools
-                .extend(parse_toml::<Tools>(args.tools_config).tools.into_iter());
-            all_settings.benchmarks.extend(
-                parse_toml::<BenchmarksConfig>(args.benchmarks_config)
-                    .benchmarks
-                    .into_iter(),
+            let (all_settings, local_env, base_dir) = load_config(
+                &args.benchmarks_config,
+                &args.tools_config,
+                &args.env_config,
             );
-            let local_env = parse_toml::<LocalConfig>(args.env_config);
-            all_settings
-                .working_dirs
-                .extend(local_env.working_dirs.into_iter());
-            all_settings.datasets.extend(local_env.datasets.into_iter());
 
             let results_dir = args.results_path.join("results-dir");
             let outputs_dir = args.results_path.join("outputs-dir");
             let logs_dir = args.results_path.join("logs-dir");
 
-            let _ = create_dir_all(&results_dir);
-            let _ = create_dir_all(&outputs_dir);
-            let _ = create_dir_all(&logs_dir);
-
-            let experiment = {
-                let mut res = None;
-                let mut multiple_choices = Vec::new();
-
-                for bench in &all_settings.benchmarks {
-                    if bench.name == args.test_name {
-                        res = Some(bench.clone());
-                    } else if bench.name.starts_with(&args.test_name) {
-                        if res.is_none() {
-                            res = Some(bench.clone());
-                        } else {
-                            if res.as_ref().unwrap().name != args.test_name {
-                                multiple_choices.push(bench.name.clone());
-                            }
-                        }
-                    }
-                }
-
-                if res.is_none() {
-                    println!("Cannot find a benchmark matching \"{}\"!", args.test_name);
-                    println!("Available benchmarks:");
-                    for bench in all_settings.benchmarks {
-                        println!("\t{}", &bench.name);
-                    }
-                    return;
-                } else if multiple_choices.len() > 0 {
-                    println!("Multiple benchmarks matching \"{}\"!", args.test_name);
-                    println!("Matching benchmarks:");
-                    println!("\t{}", res.as_ref().unwrap().name);
-                    for bench in multiple_choices {
-                        println!("\t{}", bench);
-                    }
-                    return;
-                }
-                res.unwrap()
+            let experiment = match find_benchmark(&all_settings.benchmarks, &args.test_name) {
+                Some(experiment) => experiment,
+                None => return,
             };
 
-            let include = args
-                .include
-                .map(|i| i.split(",").map(|x| x.to_string()).collect::<Vec<_>>());
-            let exclude = args
-                .exclude
-                .map(|i| i.split(",").map(|x| x.to_string()).collect::<Vec<_>>());
-
-            let datasets = filter_options(
-                "datasets",
-                experiment
-                    .datasets
-                    .iter()
-                    .map(|x| {
-                        all_settings
-                            .datasets
-                            .iter()
-                            .filter(|d| &d.name == x)
-                            .next()
-                            .expect(&format!("Cannot find a dataset with name '{}'", x))
-                    })
-                    .collect::<Vec<_>>(),
-                |d| &d.name,
-                include.as_ref().unwrap_or(&vec![]),
-                exclude.as_ref().unwrap_or(&vec![]),
+            let mut matrix = RunMatrix::new(
+                &all_settings,
+                &experiment,
+                &args.include,
+                &args.exclude,
+                &args.threads,
             );
+            if let Some(run_index) = args.run_index {
+                matrix.select_run(run_index);
+            }
+            let runs = matrix.runs();
 
-            let tools = filter_options(
-                "tools",
-                experiment
-                    .tools
-                    .iter()
-                    .map(|x| {
-                        all_settings
-                            .tools
-                            .iter()
-                            .filter(|t| &t.name == x)
-                            .next()
-                            .expect(&format!("Cannot find a tool with name '{}'", x))
-                    })
-                    .collect::<Vec<_>>(),
-                |t| &t.name,
-                include.as_ref().unwrap_or(&vec![]),
-                exclude.as_ref().unwrap_or(&vec![]),
-            );
+            let rerun_policy = RerunPolicy {
+                force: args.force,
+                failed: args.rerun_failed,
+                older_than: args.rerun_older_than,
+                tool_changed: args.rerun_if_tool_changed,
+            };
+            let archive_dir = args
+                .results_path
+                .join("archive")
+                .join(chrono::Local::now().format("%Y%m%d-%H%M%S").to_string());
+
+            if args.dry_run {
+                let entries = plan_entries(
+                    &runs,
+                    &experiment,
+                    &local_env,
+                    &base_dir,
+                    &results_dir,
+                    &logs_dir,
+                    args.memory_search,
+                    &rerun_policy,
+                );
+                match args.plan_format.as_str() {
+                    "json" => println!("{}", serde_json::to_string_pretty(&entries).unwrap()),
+                    _ => print_plan_table(&entries),
+                }
+                return;
+            }
 
-            let mut working_dirs = filter_options(
-                "working dirs",
-                experiment.working_dirs,
-                |x| &x,
-                include.as_ref().unwrap_or(&vec![]),
-                exclude.as_ref().unwrap_or(&vec![]),
-            );
+            let _ = create_dir_all(&results_dir);
+            let _ = create_dir_all(&outputs_dir);
+            let _ = create_dir_all(&logs_dir);
+
+            // Final outputs of the last completed run, evicted by the cold cache state
+            let mut previous_outputs = vec![];
+            let mut dataset_inputs = DatasetInputsCache::new(&base_dir);
 
-            for dataset in datasets {
-                for working_dir in &working_dirs {
-                    let working_dir = all_settings
-                        .working_dirs
-                        .iter()
-                        .filter(|w| &w.name == working_dir)
-                        .next()
-                        .expect(
-                            &format!("Cannot find a working dir named: {}", &working_dir).clone(),
-                        );
-
-                    let working_path = if working_dir.path.is_absolute() {
-                        working_dir.path.clone()
-                    } else {
-                        base_dir.join(&working_dir.path)
-                    };
+            'datasets: for dataset in &matrix.datasets {
+                for working_dir in &matrix.working_dirs {
+                    let working_path = resolve_working_path(&base_dir, working_dir);
 
                     let tmp_workdir = create_dir_with_guard(&working_path).expect(&format!(
                         "Cannot create working dir: {}",
@@ -353,219 +339,235 @@ fn main() {
                     ));
                     let mut keep_temp_dir = false;
 
-                    let mut input_files: Vec<_> = dataset
-                        .files
-                        .as_ref()
-                        .unwrap_or(&Vec::new())
-                        .iter()
-                        .map(|x| {
-                            let path = if x.is_absolute() {
-                                x.clone()
-                            } else {
-                                base_dir.join(x)
-                            };
-                            path
-                        })
-                        .collect();
-
-                    if let Some(lists) = &dataset.lists {
-                        for list in lists {
-                            let list = if list.is_absolute() {
-                                list.clone()
-                            } else {
-                                base_dir.join(list)
-                            };
-
-                            for line in BufReader::new(File::open(list).unwrap()).lines() {
-                                input_files.push(PathBuf::from(line.unwrap()));
-                            }
-                        }
-                    }
-
-                    if let Some(limit) = dataset.limit {
-                        input_files.truncate(limit);
-                    }
-
                     if dataset.tar.is_some() && !experiment.copy_dataset {
                         println!("Warning: tar datasets must be copied to workdir (set copy-dataset = true)");
                         continue;
                     }
 
-                    let mut dataset_copied = false;
                     let dataset_dir = tmp_workdir.as_ref().join("dataset");
                     create_dir(&dataset_dir);
 
-                    let threads = if let Some(threads) = &args.threads {
-                        threads.split(",").map(|t| t.parse().unwrap()).collect()
-                    } else {
-                        experiment.threads.clone()
-                    };
-
-                    for thread in &threads {
-                        for kval in &experiment.kvalues {
-                            for tool in &tools {
-                                let base_name = format!(
-                                    "{}_{}_K{}_{}_T{}{}",
-                                    dataset.name,
-                                    working_dir.name,
-                                    kval,
-                                    tool.name,
-                                    thread,
-                                    if dataset.query.is_some() {
-                                        format!("_query")
-                                    } else {
-                                        "".to_string()
-                                    }
-                                );
+                    // Resolved, and copied if needed, by the first run actually executed
+                    let mut local_inputs: Option<Vec<PathBuf>> = None;
+
+                    'runs: for run in runs.iter().filter(|run| {
+                        std::ptr::eq(run.dataset, dataset)
+                            && std::ptr::eq(run.working_dir, working_dir)
+                    }) {
+                        if is_interrupted() {
+                            break 'runs;
+                        }
 
-                                let results_file =
-                                    results_dir.join(&format!("{}thr-info.json", base_name));
+                        let tool = run.tool;
+                        let base_name = &run.base_name;
 
-                                if results_file.exists() {
+                        let results_file =
+                            results_dir.join(&results_file_name(base_name, args.memory_search));
+
+                        if results_file.exists() {
+                            match rerun_policy
+                                .rerun_reason(&results_file, &Runner::tool_path(&base_dir, tool))
+                            {
+                                None => {
                                     println!(
                                         "File {} already exists, skipping test!",
                                         results_file.file_name().unwrap().to_str().unwrap()
                                     );
                                     continue;
                                 }
-
-                                if !dataset_copied && experiment.copy_dataset {
+                                Some(reason) => {
                                     println!(
-                                        "Copying dataset for bench: {}",
-                                        results_file.file_name().unwrap().to_str().unwrap()
+                                        "Rerunning {} ({}), previous results archived in {}",
+                                        base_name,
+                                        reason,
+                                        archive_dir.display()
                                     );
+                                    archive_run_files(&args.results_path, &archive_dir, base_name);
+                                }
+                            }
+                        }
 
-                                    dataset_copied = true;
-
-                                    if let Some(tarball) = &dataset.tar {
-                                        for entry in tar::Archive::new(File::open(tarball).unwrap())
-                                            .entries()
-                                            .unwrap()
-                                            .filter(|d| {
-                                                d.is_ok()
-                                                    && d.as_ref()
-                                                        .unwrap()
-                                                        .path()
-                                                        .unwrap()
-                                                        .extension()
-                                                        .is_some()
-                                            })
-                                            .take(
-                                                dataset
-                                                    .limit
-                                                    .map(|limit| limit - input_files.len())
-                                                    .unwrap_or(usize::MAX),
-                                            )
-                                        {
-                                            let mut entry = entry.unwrap();
-                                            let tmp_path = entry.path().unwrap();
-                                            let file_name = tmp_path.file_name().unwrap();
-
-                                            let dest_file = dataset_dir.join(file_name);
-
-                                            entry.unpack(&dest_file).unwrap();
-                                            input_files.push(dest_file);
+                        // Checked before copying the dataset, which may itself fill the disk
+                        if let Some(min_free_gb) = experiment.min_free_gb {
+                            if !has_free_space(tmp_workdir.as_ref(), min_free_gb) {
+                                println!("Skipping {}, not enough free disk space!", base_name);
+                                continue;
+                            }
+                        }
+
+                        if local_inputs.is_none() {
+                            let inputs = dataset_inputs.inputs(dataset);
+                            local_inputs = Some(if experiment.copy_dataset {
+                                println!(
+                                    "Copying dataset for bench: {}",
+                                    results_file.file_name().unwrap().to_str().unwrap()
+                                );
+                                materialize_inputs(inputs, &dataset_dir, true)
+                            } else {
+                                inputs
+                                    .iter()
+                                    .map(|input| input.local_path(&dataset_dir, false))
+                                    .collect()
+                            });
+                        }
+                        let input_files = local_inputs.as_ref().unwrap();
+
+                        let temp_dir = run.temp_dir(tmp_workdir.as_ref());
+                        let out_dir = run.out_dir(tmp_workdir.as_ref());
+
+                        let make_parameters = |log_name: String, trace_file: Option<PathBuf>| {
+                            run.parameters(
+                                &experiment,
+                                &local_env,
+                                tmp_workdir.as_ref(),
+                                logs_dir.join(&log_name),
+                                trace_file,
+                            )
+                        };
+
+                        if args.memory_search {
+                            let search = experiment
+                                .memory_search
+                                .as_ref()
+                                .expect("The benchmark has no memory-search section");
+
+                            let search_results = search_min_memory(
+                                search,
+                                run.metadata(&experiment),
+                                |probe_idx, memory_gb| {
+                                    prepare_run_dirs(&temp_dir, &out_dir);
+
+                                    let mut parameters = make_parameters(
+                                        format!("{}-memsearch{}.log", base_name, probe_idx),
+                                        None,
+                                    );
+                                    parameters.memory_gb = Some(memory_gb);
+                                    parameters.previous_outputs = previous_outputs.clone();
+                                    match search.method.unwrap_or(MemoryLimitMethod::Rlimit) {
+                                        MemoryLimitMethod::Rlimit => {
+                                            parameters.address_space_limit_gb = Some(memory_gb)
+                                        }
+                                        MemoryLimitMethod::Cgroup => {
+                                            parameters.memory_limit_gb = Some(memory_gb)
                                         }
                                     }
 
-                                    let mut new_input_files = Vec::new();
+                                    let results = Runner::run_tool(
+                                        &base_dir,
+                                        (*tool).clone(),
+                                        dataset.name.clone(),
+                                        &input_files,
+                                        parameters,
+                                    );
 
-                                    for file in input_files {
-                                        let name = Path::new(&file).file_name().unwrap();
+                                    remove_dir_all(&temp_dir);
+                                    remove_dir_all(&out_dir);
+                                    results
+                                },
+                            );
+
+                            if search_results.interrupted {
+                                println!(
+                                    "Memory search of {} interrupted, resume it with --rerun-failed",
+                                    base_name
+                                );
+                            }
+                            write_json(&results_file, &search_results);
+                            continue;
+                        }
 
-                                        let new_file = dataset_dir.join(name);
-                                        new_input_files.push(new_file.clone());
+                        let repetitions = experiment.repetitions.unwrap_or(1).max(1);
+                        let warmup_runs = experiment.warmup_runs.unwrap_or(0);
+                        let total_runs = warmup_runs + repetitions;
 
-                                        if new_file == file {
-                                            // Skip files already in dest dir
-                                            continue;
-                                        }
+                        let reps_dir = results_dir.join(&format!("{}thr-reps", base_name));
+                        if repetitions > 1 {
+                            create_dir_all(&reps_dir).unwrap();
+                        }
 
-                                        std::fs::copy(&file, &new_file).expect(&format!(
-                                            "Cannot copy file: {} to working dir {}",
-                                            file.display(),
-                                            new_file.display()
-                                        ));
-                                    }
+                        let mut runs = Vec::new();
 
-                                    input_files = new_input_files
-                                }
+                        for run_idx in 0..total_runs {
+                            let is_warmup = run_idx < warmup_runs;
+                            let is_last_run = run_idx == total_runs - 1;
 
-                                let temp_dir =
-                                    tmp_workdir.as_ref().join(&format!("{}thr_temp", base_name));
-                                let out_dir =
-                                    tmp_workdir.as_ref().join(&format!("{}thr_out", base_name));
-                                if temp_dir.exists()
-                                    && temp_dir.read_dir().unwrap().next().is_some()
-                                {
-                                    panic!(
-                                        "Temporary directory {} not empty!, aborting (file: {})",
-                                        temp_dir.display(),
-                                        temp_dir
-                                            .read_dir()
-                                            .unwrap()
-                                            .next()
-                                            .unwrap()
-                                            .unwrap()
-                                            .file_name()
-                                            .into_string()
-                                            .unwrap()
-                                    );
-                                }
-                                if out_dir.exists() && out_dir.read_dir().unwrap().next().is_some()
-                                {
-                                    panic!(
-                                        "Output directory {} not empty!, aborting",
-                                        out_dir.display()
+                            // The copied dataset and the previous runs may have used the space checked at the start
+                            if let Some(min_free_gb) = experiment.min_free_gb {
+                                if !has_free_space(tmp_workdir.as_ref(), min_free_gb) {
+                                    println!(
+                                        "Stopping {} before run {} of {}, not enough free disk space!",
+                                        base_name,
+                                        run_idx + 1,
+                                        total_runs
                                     );
+                                    break;
                                 }
-                                create_dir_all(&temp_dir);
-                                create_dir_all(&out_dir);
-
-                                let results = Runner::run_tool(
-                                    &base_dir,
-                                    (*tool).clone(),
-                                    dataset.name.clone(),
-                                    &input_files,
-                                    Parameters {
-                                        max_threads: *thread,
-                                        k: *kval,
-                                        multiplicity: experiment.min_multiplicity,
-                                        output_file: out_dir
-                                            .join(&format!("{}thr.fa", base_name))
-                                            .into_os_string()
-                                            .into_string()
-                                            .unwrap(),
-                                        canonical_file: out_dir
-                                            .join(&format!("canonical_{}thr.fa", base_name))
-                                            .into_os_string()
-                                            .into_string()
-                                            .unwrap(),
-                                        temp_dir: temp_dir
-                                            .clone()
-                                            .into_os_string()
-                                            .into_string()
-                                            .unwrap(),
-                                        log_file: logs_dir
-                                            .clone()
-                                            .join(&format!("{}.log", base_name)),
-                                        memory_gb: experiment.max_memory,
-                                        size_check_time: Duration::from_millis(
-                                            experiment.size_check_time,
-                                        ),
-                                        query_files: (
-                                            dataset.query.clone(),
-                                            dataset.colorfile.clone(),
-                                        ),
-                                    },
+                            }
+
+                            let trace_file = if is_warmup {
+                                None
+                            } else if repetitions > 1 {
+                                Some(
+                                    reps_dir
+                                        .join(&format!("rep{}-trace.csv", run_idx - warmup_runs)),
+                                )
+                            } else {
+                                Some(results_dir.join(&format!("{}thr-trace.csv", base_name)))
+                            };
+
+                            let log_name = if is_warmup {
+                                format!("{}-warmup{}.log", base_name, run_idx)
+                            } else if repetitions > 1 {
+                                format!("{}-rep{}.log", base_name, run_idx - warmup_runs)
+                            } else {
+                                format!("{}.log", base_name)
+                            };
+
+                            if is_warmup {
+                                println!("Warmup run {}/{}", run_idx + 1, warmup_runs);
+                            } else if repetitions > 1 {
+                                println!(
+                                    "Repetition {}/{}",
+                                    run_idx - warmup_runs + 1,
+                                    repetitions
                                 );
+                            }
 
-                                if !experiment.keep_temp.unwrap_or(false) {
-                                    remove_dir_all(&temp_dir);
-                                } else {
+                            prepare_run_dirs(&temp_dir, &out_dir);
+
+                            let mut parameters = make_parameters(log_name, trace_file);
+                            parameters.previous_outputs = previous_outputs.clone();
+                            let mut results = Runner::run_tool(
+                                &base_dir,
+                                (*tool).clone(),
+                                dataset.name.clone(),
+                                &input_files,
+                                parameters,
+                            );
+
+                            // A completed last run is recorded as usual, any other run is incomplete
+                            if is_interrupted()
+                                && !(is_last_run && results.outcome == RunOutcome::Completed)
+                            {
+                                results.outcome = RunOutcome::Interrupted;
+                                if experiment.keep_temp.unwrap_or(false) {
                                     keep_temp_dir = true;
+                                } else {
+                                    remove_dir_all(&temp_dir);
                                 }
+                                remove_dir_all(&out_dir);
+                                write_json(&results_file, &results);
+                                break 'runs;
+                            }
 
+                            // Intermediate runs always start from an empty temp dir
+                            if !is_last_run || !experiment.keep_temp.unwrap_or(false) {
+                                remove_dir_all(&temp_dir);
+                            } else {
+                                keep_temp_dir = true;
+                            }
+
+                            if is_last_run {
                                 let final_out_dir =
                                     outputs_dir.join(&format!("{}thr_out", base_name));
                                 create_dir_all(&final_out_dir).unwrap();
@@ -580,22 +582,52 @@ fn main() {
                                         std::fs::remove_file(file.path()).unwrap();
                                     }
                                 }
-                                remove_dir_all(&out_dir);
+                                previous_outputs = vec![final_out_dir];
+                            }
+                            remove_dir_all(&out_dir);
+
+                            if is_warmup {
+                                continue;
+                            }
 
-                                File::create(results_file)
-                                    .unwrap()
-                                    .write_all(
-                                        serde_json::to_string_pretty(&results).unwrap().as_bytes(),
-                                    )
-                                    .unwrap();
+                            if repetitions > 1 {
+                                write_json(
+                                    &reps_dir.join(&format!("rep{}.json", run_idx - warmup_runs)),
+                                    &results,
+                                );
                             }
+
+                            runs.push(results);
+                        }
+
+                        if runs.is_empty() {
+                            continue;
                         }
+
+                        let summary = if repetitions > 1 {
+                            Some(RunsSummary::from_runs(&runs, warmup_runs))
+                        } else {
+                            None
+                        };
+
+                        let mut results = runs.swap_remove(median_run_index(&runs));
+                        results.summary = summary;
+
+                        write_json(&results_file, &results);
                     }
                     if keep_temp_dir {
                         std::mem::forget(tmp_workdir);
                     }
+                    if is_interrupted() {
+                        break 'datasets;
+                    }
                 }
             }
+
+            if is_interrupted() {
+                println!("Benchmark interrupted!");
+                exit(130);
+            }
         }
         ExtendedCli::Canonicalize(args) => {
             if args.output.exists() && !args.force {
@@ -604,7 +636,9 @@ fn main() {
 
             canonical_kmers::canonicalize(args.input, args.output, args.kval, args.links);
         }
+        ExtendedCli::Submit(args) => submit(args),
         ExtendedCli::MakeTable(args) => make_table(args),
+        ExtendedCli::MigrateResults(args) => migrate_results(args),
         ExtendedCli::DatasetStats(args) => compute_dataset_stats(args),
         ExtendedCli::CompareEulertigs(args) => compare_eulertigs(args),
         ExtendedCli::RandomizeFasta(args) => randomize_fasta(args),
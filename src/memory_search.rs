@@ -0,0 +1,96 @@
+use crate::config::{MemoryLimitMethod, MemorySearch};
+use crate::process::is_interrupted;
+use crate::runner::{RunMetadata, RunOutcome, RunResults, RESULTS_SCHEMA_VERSION};
+use serde::{Deserialize, Serialize};
+
+const DEFAULT_PRECISION_GB: f64 = 0.5;
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MemoryProbe {
+    pub memory_gb: f64,
+    pub outcome: RunOutcome,
+    pub real_time_secs: f64,
+    pub max_memory_gb: f64,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct MemorySearchResults {
+    pub schema_version: u32,
+    pub metadata: RunMetadata,
+    pub method: MemoryLimitMethod,
+    pub lower_gb: f64,
+    pub upper_gb: f64,
+    pub precision_gb: f64,
+    /// Smallest probed memory budget that allowed the tool to complete, None if even the upper bound failed
+    pub min_successful_memory_gb: Option<f64>,
+    /// Whether the search was stopped by an interruption, the budget found so far is not final
+    #[serde(default)]
+    pub interrupted: bool,
+    pub probes: Vec<MemoryProbe>,
+}
+
+fn probe(
+    probes: &mut Vec<MemoryProbe>,
+    run: &mut impl FnMut(usize, f64) -> RunResults,
+    memory_gb: f64,
+) -> bool {
+    println!("Probing memory budget of {:.2}GB", memory_gb);
+    let results = run(probes.len(), memory_gb);
+    println!(
+        "Memory budget of {:.2}GB => {:?} in {:.2}s",
+        memory_gb, results.outcome, results.real_time_secs
+    );
+
+    probes.push(MemoryProbe {
+        memory_gb,
+        outcome: results.outcome,
+        real_time_secs: results.real_time_secs,
+        max_memory_gb: results.max_memory_gb,
+    });
+    results.outcome == RunOutcome::Completed
+}
+
+/// Bisects the memory budget between the bounds of the search, calling `run` with the probe index and budget.
+/// Completion is assumed to be monotonic in the memory budget
+pub fn search_min_memory(
+    search: &MemorySearch,
+    metadata: RunMetadata,
+    mut run: impl FnMut(usize, f64) -> RunResults,
+) -> MemorySearchResults {
+    let precision_gb = search.precision_gb.unwrap_or(DEFAULT_PRECISION_GB);
+    let mut probes = vec![];
+
+    let min_successful_memory_gb = if !probe(&mut probes, &mut run, search.upper_gb) {
+        None
+    } else if is_interrupted() {
+        Some(search.upper_gb)
+    } else if probe(&mut probes, &mut run, search.lower_gb) {
+        Some(search.lower_gb)
+    } else {
+        let mut failing = search.lower_gb;
+        let mut succeeding = search.upper_gb;
+
+        // An interrupted search reports the smallest budget found so far
+        while succeeding - failing > precision_gb && !is_interrupted() {
+            let middle = (failing + succeeding) / 2.0;
+            if probe(&mut probes, &mut run, middle) {
+                succeeding = middle;
+            } else {
+                failing = middle;
+            }
+        }
+        Some(succeeding)
+    };
+
+    MemorySearchResults {
+        schema_version: RESULTS_SCHEMA_VERSION,
+        metadata,
+        method: search.method.unwrap_or(MemoryLimitMethod::Rlimit),
+        lower_gb: search.lower_gb,
+        upper_gb: search.upper_gb,
+        precision_gb,
+        min_successful_memory_gb,
+        interrupted: is_interrupted(),
+        probes,
+    }
+}
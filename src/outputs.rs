@@ -0,0 +1,89 @@
+use crate::config::{OutputFormat, OutputSpec};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::PathBuf;
+
+/// Output spec with the placeholders of its pattern already rendered
+pub struct OutputPattern {
+    pub pattern: String,
+    pub format: OutputFormat,
+    pub primary: bool,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DetectedOutput {
+    pub path: String,
+    pub format: OutputFormat,
+    pub primary: bool,
+}
+
+/// Outputs assumed for tools without an explicit spec, any fasta or gfa file starting with the output name
+pub fn default_output_specs() -> Vec<OutputSpec> {
+    vec![
+        OutputSpec {
+            pattern: "<OUTPUT_FILE>".to_string(),
+            format: OutputFormat::Fasta,
+            primary: None,
+        },
+        OutputSpec {
+            pattern: "<OUTPUT_FILE>*.fa".to_string(),
+            format: OutputFormat::Fasta,
+            primary: None,
+        },
+        OutputSpec {
+            pattern: "<OUTPUT_FILE>*.gfa".to_string(),
+            format: OutputFormat::Gfa1,
+            primary: None,
+        },
+    ]
+}
+
+/// Lists the files matching the patterns, a file matching multiple patterns is assigned to the first one
+pub fn detect_outputs(patterns: &[OutputPattern]) -> Vec<DetectedOutput> {
+    let mut seen = HashSet::new();
+    let mut outputs = vec![];
+
+    for pattern in patterns {
+        let mut paths: Vec<PathBuf> = glob::glob(&pattern.pattern)
+            .unwrap_or_else(|err| panic!("Invalid output pattern {}: {}", pattern.pattern, err))
+            .filter_map(|path| path.ok())
+            .filter(|path| path.is_file())
+            .collect();
+        paths.sort();
+
+        for path in paths {
+            if seen.insert(path.clone()) {
+                outputs.push(DetectedOutput {
+                    path: path.to_string_lossy().into_owned(),
+                    format: pattern.format,
+                    primary: pattern.primary,
+                });
+            }
+        }
+    }
+    outputs
+}
+
+/// A run is complete when the primary output exists, or any output if none is marked as primary
+pub fn has_required_outputs(patterns: &[OutputPattern], outputs: &[DetectedOutput]) -> bool {
+    if patterns.iter().any(|p| p.primary) {
+        outputs.iter().any(|o| o.primary)
+    } else {
+        !outputs.is_empty()
+    }
+}
+
+/// Output to canonicalize, the primary one if in fasta format, otherwise the first fasta output when no
+/// output is marked as primary
+pub fn canonicalizable_output<'a>(
+    patterns: &[OutputPattern],
+    outputs: &'a [DetectedOutput],
+) -> Option<&'a DetectedOutput> {
+    if patterns.iter().any(|p| p.primary) {
+        outputs
+            .iter()
+            .find(|o| o.primary && o.format == OutputFormat::Fasta)
+    } else {
+        outputs.iter().find(|o| o.format == OutputFormat::Fasta)
+    }
+}
@@ -0,0 +1,97 @@
+use crate::config::CacheState;
+use serde::{Deserialize, Serialize};
+use std::fs::File;
+use std::io::{self, Read};
+use std::os::unix::io::AsRawFd;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// Cache state actually applied before a run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct CacheControl {
+    pub state: CacheState,
+    /// Number of files evicted from or loaded into the page cache
+    pub files: usize,
+    /// Whether the whole page cache was dropped, only possible when running as root
+    pub dropped_caches: bool,
+}
+
+fn evict_file(path: &Path) -> io::Result<()> {
+    let file = File::open(path)?;
+    // Dirty pages are not evicted, e.g. the ones of a dataset just copied in the working dir
+    if unsafe { libc::fdatasync(file.as_raw_fd()) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    match unsafe { libc::posix_fadvise(file.as_raw_fd(), 0, 0, libc::POSIX_FADV_DONTNEED) } {
+        0 => Ok(()),
+        err => Err(io::Error::from_raw_os_error(err)),
+    }
+}
+
+fn preload_file(path: &Path) -> io::Result<()> {
+    let mut file = File::open(path)?;
+    let mut buffer = vec![0; 1024 * 1024];
+    while file.read(&mut buffer)? > 0 {}
+    Ok(())
+}
+
+fn drop_caches() -> bool {
+    if unsafe { libc::geteuid() } != 0 {
+        return false;
+    }
+    unsafe { libc::sync() };
+    std::fs::write("/proc/sys/vm/drop_caches", "3").is_ok()
+}
+
+/// Lists the regular files, descending into the directories
+fn list_files(paths: &[PathBuf]) -> Vec<PathBuf> {
+    paths
+        .iter()
+        .flat_map(|path| WalkDir::new(path).into_iter().filter_map(|e| e.ok()))
+        .filter(|entry| entry.file_type().is_file())
+        .map(|entry| entry.into_path())
+        .collect()
+}
+
+/// Brings the page cache in the requested state. `cold` evicts the inputs and the
+/// outputs and intermediates of the previous runs, `warm` reads the inputs once
+pub fn apply_cache_state(
+    state: CacheState,
+    input_files: &[PathBuf],
+    previous_outputs: &[PathBuf],
+) -> CacheControl {
+    let (files, dropped_caches) = match state {
+        CacheState::Cold => {
+            let files = list_files(&[input_files, previous_outputs].concat());
+            for file in &files {
+                if let Err(err) = evict_file(file) {
+                    println!(
+                        "WARNING: Cannot evict {} from the page cache: {}",
+                        file.display(),
+                        err
+                    );
+                }
+            }
+            (files, drop_caches())
+        }
+        CacheState::Warm => {
+            let files = list_files(input_files);
+            for file in &files {
+                if let Err(err) = preload_file(file) {
+                    println!(
+                        "WARNING: Cannot read {} into the page cache: {}",
+                        file.display(),
+                        err
+                    );
+                }
+            }
+            (files, false)
+        }
+    };
+
+    CacheControl {
+        state,
+        files: files.len(),
+        dropped_caches,
+    }
+}
@@ -0,0 +1,638 @@
+use crate::config::{
+    Benchmark, BenchmarksConfig, Config, Dataset, LocalConfig, Tool, Tools, WorkingDir,
+};
+use crate::parse_toml;
+use crate::runner::{Parameters, RunMetadata, TEMPLATE_PLACEHOLDERS};
+use std::collections::BTreeMap;
+use std::env::current_dir;
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::time::Duration;
+
+/// Directory of the local config, the relative paths in it are resolved from there
+pub fn config_base_dir(env_config: &Path) -> PathBuf {
+    if env_config.is_absolute() {
+        env_config.parent().unwrap().to_path_buf()
+    } else {
+        current_dir()
+            .unwrap()
+            .join(env_config)
+            .parent()
+            .unwrap()
+            .to_path_buf()
+    }
+}
+
+/// Loads the three configuration files, returning the merged config, the local config and the
+/// directory relative paths are resolved against
+pub fn load_config(
+    benchmarks_config: &Path,
+    tools_config: &Path,
+    env_config: &Path,
+) -> (Config, LocalConfig, PathBuf) {
+    let base_dir = config_base_dir(env_config);
+    let local_env = parse_toml::<LocalConfig>(env_config.to_path_buf());
+    let config = Config {
+        tools: parse_toml::<Tools>(tools_config.to_path_buf()).tools,
+        datasets: local_env.datasets.clone(),
+        benchmarks: parse_toml::<BenchmarksConfig>(benchmarks_config.to_path_buf()).benchmarks,
+        working_dirs: local_env.working_dirs.clone(),
+    };
+
+    (config, local_env, base_dir)
+}
+
+/// Finds the benchmark with the given name, or the only one starting with it.
+/// Prints the available choices and returns None if there is no unique match
+pub fn find_benchmark(benchmarks: &[Benchmark], test_name: &str) -> Option<Benchmark> {
+    let mut res = None;
+    let mut multiple_choices = Vec::new();
+
+    for bench in benchmarks {
+        if bench.name == test_name {
+            res = Some(bench.clone());
+        } else if bench.name.starts_with(test_name) {
+            if res.is_none() {
+                res = Some(bench.clone());
+            } else {
+                if res.as_ref().unwrap().name != test_name {
+                    multiple_choices.push(bench.name.clone());
+                }
+            }
+        }
+    }
+
+    if res.is_none() {
+        println!("Cannot find a benchmark matching \"{}\"!", test_name);
+        println!("Available benchmarks:");
+        for bench in benchmarks {
+            println!("\t{}", &bench.name);
+        }
+        return None;
+    } else if multiple_choices.len() > 0 {
+        println!("Multiple benchmarks matching \"{}\"!", test_name);
+        println!("Matching benchmarks:");
+        println!("\t{}", res.as_ref().unwrap().name);
+        for bench in multiple_choices {
+            println!("\t{}", bench);
+        }
+        return None;
+    }
+    res
+}
+
+fn filter_options<T>(
+    name: &str,
+    options: Vec<T>,
+    mapper: fn(&T) -> &String,
+    include: &Vec<String>,
+    exclude: &Vec<String>,
+) -> Vec<T> {
+    let include_matches = options.iter().any(|x| include.contains(mapper(x)));
+    let exclude_matches = options.iter().any(|x| exclude.contains(mapper(x)));
+
+    if include_matches && exclude_matches {
+        println!(
+            "Warning: both includes and excludes match on parameter: {}",
+            name
+        );
+    }
+
+    options
+        .into_iter()
+        .filter(|x| {
+            if include_matches {
+                include.contains(mapper(x))
+            } else if exclude_matches {
+                !exclude.contains(mapper(x))
+            } else {
+                true
+            }
+        })
+        .collect()
+}
+
+fn split_list(list: &Option<String>) -> Vec<String> {
+    list.as_ref()
+        .map(|i| i.split(",").map(|x| x.to_string()).collect())
+        .unwrap_or_default()
+}
+
+/// Parameter values of a benchmark after applying the command line filters
+pub struct RunMatrix {
+    pub datasets: Vec<Dataset>,
+    pub working_dirs: Vec<WorkingDir>,
+    pub threads: Vec<usize>,
+    pub kvalues: Vec<usize>,
+    /// Every combination of the swept parameters, from the name in the config to the value
+    pub sweep: Vec<BTreeMap<String, String>>,
+    pub tools: Vec<Tool>,
+}
+
+/// A single run of the matrix, the index is stable for the same benchmark and filters
+pub struct PlannedRun<'a> {
+    pub index: usize,
+    pub dataset: &'a Dataset,
+    pub working_dir: &'a WorkingDir,
+    pub threads: usize,
+    pub k: usize,
+    pub sweep: &'a BTreeMap<String, String>,
+    pub tool: &'a Tool,
+    pub base_name: String,
+}
+
+/// Swept parameters overriding the scalar options of the benchmark instead of adding a placeholder
+pub const SWEEP_OVERRIDES: &[&str] = &["multiplicity", "max-memory"];
+
+/// Name of the template placeholder of a swept parameter
+pub fn sweep_variable_name(name: &str) -> String {
+    name.to_uppercase().replace('-', "_")
+}
+
+/// Form of a swept value in the run names
+fn sweep_value_name(value: &str) -> String {
+    value.replace(['/', '_'], "-")
+}
+
+/// Checks that the swept parameters neither misspell the overrides nor shadow the placeholders
+/// of the templates, and that every combination gets a distinct run name
+fn validate_sweep(sweep: &Option<BTreeMap<String, Vec<toml::Value>>>) {
+    let mut variables = BTreeMap::new();
+    for (name, values) in sweep.iter().flatten() {
+        let variable = sweep_variable_name(name);
+        if let Some(canonical) = SWEEP_OVERRIDES
+            .iter()
+            .find(|canonical| sweep_variable_name(canonical) == variable)
+        {
+            if name != canonical {
+                panic!("Swept parameter {} must be written {}", name, canonical);
+            }
+        } else if TEMPLATE_PLACEHOLDERS.contains(&variable.as_str()) {
+            panic!("Swept parameter {} conflicts with <{}>", name, variable);
+        }
+        if let Some(other) = variables.insert(variable.clone(), name) {
+            panic!(
+                "Swept parameters {} and {} are both exposed as <{}>",
+                other, name, variable
+            );
+        }
+
+        let mut value_names = BTreeMap::new();
+        for value in values {
+            let value = sweep_value_to_string(name, value);
+            let valid = match name.as_str() {
+                "multiplicity" => value.parse::<usize>().is_ok(),
+                "max-memory" => value.parse::<f64>().is_ok(),
+                _ => true,
+            };
+            if !valid {
+                panic!("Invalid value '{}' for swept parameter {}", value, name);
+            }
+            if let Some(other) = value_names.insert(sweep_value_name(&value), value.clone()) {
+                panic!(
+                    "Values '{}' and '{}' of swept parameter {} give the same run names",
+                    other, value, name
+                );
+            }
+        }
+    }
+}
+
+fn sweep_value_to_string(name: &str, value: &toml::Value) -> String {
+    match value {
+        toml::Value::String(value) => value.clone(),
+        toml::Value::Integer(value) => value.to_string(),
+        toml::Value::Float(value) => value.to_string(),
+        toml::Value::Boolean(value) => value.to_string(),
+        _ => panic!("Unsupported value {} for swept parameter {}", value, name),
+    }
+}
+
+/// All the combinations of the swept parameters, a single empty one if nothing is swept
+fn sweep_combinations(
+    sweep: &Option<BTreeMap<String, Vec<toml::Value>>>,
+) -> Vec<BTreeMap<String, String>> {
+    let mut combinations = vec![BTreeMap::new()];
+    for (name, values) in sweep.iter().flatten() {
+        combinations = combinations
+            .into_iter()
+            .flat_map(|combination| {
+                values.iter().map(move |value| {
+                    let mut combination = combination.clone();
+                    combination.insert(name.clone(), sweep_value_to_string(name, value));
+                    combination
+                })
+            })
+            .collect();
+    }
+    combinations
+}
+
+pub fn run_base_name(
+    dataset: &Dataset,
+    working_dir: &WorkingDir,
+    k: usize,
+    tool: &Tool,
+    threads: usize,
+    sweep: &BTreeMap<String, String>,
+) -> String {
+    format!(
+        "{}_{}_K{}_{}_T{}{}{}",
+        dataset.name,
+        working_dir.name,
+        k,
+        tool.name,
+        threads,
+        if dataset.query.is_some() {
+            format!("_query")
+        } else {
+            "".to_string()
+        },
+        sweep
+            .iter()
+            .map(|(name, value)| format!("_{}={}", name.replace('_', "-"), sweep_value_name(value)))
+            .collect::<String>()
+    )
+}
+
+pub fn resolve_path(base_dir: &Path, path: &Path) -> PathBuf {
+    if path.is_absolute() {
+        path.to_path_buf()
+    } else {
+        base_dir.join(path)
+    }
+}
+
+pub fn resolve_working_path(base_dir: &Path, working_dir: &WorkingDir) -> PathBuf {
+    resolve_path(base_dir, &working_dir.path)
+}
+
+pub fn results_file_name(base_name: &str, memory_search: bool) -> String {
+    if memory_search {
+        format!("{}thr-memsearch.json", base_name)
+    } else {
+        format!("{}thr-info.json", base_name)
+    }
+}
+
+impl PlannedRun<'_> {
+    /// Memory budget of the run, the swept max-memory overrides the benchmark one
+    pub fn max_memory_gb(&self, benchmark: &Benchmark) -> Option<f64> {
+        self.swept_value("max-memory").or(benchmark.max_memory)
+    }
+
+    fn swept_value<T: FromStr>(&self, name: &str) -> Option<T> {
+        self.sweep.get(name).map(|value| {
+            value.parse().unwrap_or_else(|_| {
+                panic!("Invalid value '{}' for swept parameter {}", value, name)
+            })
+        })
+    }
+
+    pub fn temp_dir(&self, working_path: &Path) -> PathBuf {
+        working_path.join(&format!("{}thr_temp", self.base_name))
+    }
+
+    pub fn out_dir(&self, working_path: &Path) -> PathBuf {
+        working_path.join(&format!("{}thr_out", self.base_name))
+    }
+
+    /// File listing the input files, passed to the tools as <INPUT_FILES_LIST>
+    pub fn input_files_list(&self, working_path: &Path) -> PathBuf {
+        working_path.join(&format!("{}thr_input-files.txt", self.base_name))
+    }
+
+    pub fn metadata(&self, benchmark: &Benchmark) -> RunMetadata {
+        RunMetadata {
+            benchmark: benchmark.name.clone(),
+            dataset: self.dataset.name.clone(),
+            working_dir: self.working_dir.name.clone(),
+            k: self.k,
+            tool: self.tool.name.clone(),
+            tool_group: self
+                .tool
+                .group
+                .clone()
+                .unwrap_or_else(|| self.tool.name.clone()),
+            threads: self.threads,
+            multiplicity: self
+                .swept_value("multiplicity")
+                .unwrap_or(benchmark.min_multiplicity),
+            query: self.dataset.query.is_some(),
+            sweep: self.sweep.clone(),
+        }
+    }
+
+    pub fn parameters(
+        &self,
+        benchmark: &Benchmark,
+        local_env: &LocalConfig,
+        working_path: &Path,
+        log_file: PathBuf,
+        trace_file: Option<PathBuf>,
+    ) -> Parameters {
+        let out_dir = self.out_dir(working_path);
+        let metadata = self.metadata(benchmark);
+        Parameters {
+            max_threads: self.threads,
+            k: self.k,
+            multiplicity: metadata.multiplicity,
+            output_file: out_dir
+                .join(&format!("{}thr.fa", self.base_name))
+                .into_os_string()
+                .into_string()
+                .unwrap(),
+            canonical_file: out_dir
+                .join(&format!("canonical_{}thr.fa", self.base_name))
+                .into_os_string()
+                .into_string()
+                .unwrap(),
+            temp_dir: self
+                .temp_dir(working_path)
+                .into_os_string()
+                .into_string()
+                .unwrap(),
+            input_files_list: self.input_files_list(working_path),
+            log_file,
+            trace_file,
+            memory_gb: self.max_memory_gb(benchmark),
+            size_check_time: Duration::from_millis(benchmark.size_check_time),
+            query_files: (self.dataset.query.clone(), self.dataset.colorfile.clone()),
+            timeout: self
+                .tool
+                .timeout
+                .or(benchmark.timeout)
+                .map(Duration::from_secs),
+            memory_limit_gb: benchmark.memory_limit_gb,
+            disk_limit_gb: benchmark.max_disk_gb,
+            memory_kill_gb: benchmark.memory_kill_gb,
+            address_space_limit_gb: None,
+            cgroup_root: local_env.cgroup_root.clone(),
+            cache_state: benchmark.cache_state,
+            previous_outputs: vec![],
+            disk_usage_method: benchmark.disk_usage_method,
+            metadata,
+        }
+    }
+}
+
+impl RunMatrix {
+    /// Expands the benchmark, `include` and `exclude` are comma separated names of datasets,
+    /// tools or working dirs, and `threads` overrides the thread counts of the benchmark
+    pub fn new(
+        config: &Config,
+        benchmark: &Benchmark,
+        include: &Option<String>,
+        exclude: &Option<String>,
+        threads: &Option<String>,
+    ) -> Self {
+        let include = split_list(include);
+        let exclude = split_list(exclude);
+
+        let datasets = filter_options(
+            "datasets",
+            benchmark
+                .datasets
+                .iter()
+                .map(|x| {
+                    config
+                        .datasets
+                        .iter()
+                        .filter(|d| &d.name == x)
+                        .next()
+                        .expect(&format!("Cannot find a dataset with name '{}'", x))
+                        .clone()
+                })
+                .collect::<Vec<_>>(),
+            |d| &d.name,
+            &include,
+            &exclude,
+        );
+
+        let tools = filter_options(
+            "tools",
+            benchmark
+                .tools
+                .iter()
+                .map(|x| {
+                    config
+                        .tools
+                        .iter()
+                        .filter(|t| &t.name == x)
+                        .next()
+                        .expect(&format!("Cannot find a tool with name '{}'", x))
+                        .clone()
+                })
+                .collect::<Vec<_>>(),
+            |t| &t.name,
+            &include,
+            &exclude,
+        );
+
+        let working_dirs = filter_options(
+            "working dirs",
+            benchmark.working_dirs.clone(),
+            |x| &x,
+            &include,
+            &exclude,
+        )
+        .iter()
+        .map(|working_dir| {
+            config
+                .working_dirs
+                .iter()
+                .filter(|w| &w.name == working_dir)
+                .next()
+                .expect(&format!("Cannot find a working dir named: {}", &working_dir).clone())
+                .clone()
+        })
+        .collect();
+
+        let threads = if let Some(threads) = threads {
+            threads.split(",").map(|t| t.parse().unwrap()).collect()
+        } else {
+            benchmark.threads.clone()
+        };
+
+        validate_sweep(&benchmark.sweep);
+
+        Self {
+            datasets,
+            working_dirs,
+            threads,
+            kvalues: benchmark.kvalues.clone(),
+            sweep: sweep_combinations(&benchmark.sweep),
+            tools,
+        }
+    }
+
+    /// All the runs in execution order
+    pub fn runs(&self) -> Vec<PlannedRun> {
+        let mut runs = vec![];
+        for dataset in &self.datasets {
+            for working_dir in &self.working_dirs {
+                for &threads in &self.threads {
+                    for &k in &self.kvalues {
+                        for sweep in &self.sweep {
+                            for tool in &self.tools {
+                                runs.push(PlannedRun {
+                                    index: runs.len(),
+                                    dataset,
+                                    working_dir,
+                                    threads,
+                                    k,
+                                    sweep,
+                                    tool,
+                                    base_name: run_base_name(
+                                        dataset,
+                                        working_dir,
+                                        k,
+                                        tool,
+                                        threads,
+                                        sweep,
+                                    ),
+                                });
+                            }
+                        }
+                    }
+                }
+            }
+        }
+        runs
+    }
+
+    /// Restricts the matrix to the run with the given index
+    pub fn select_run(&mut self, index: usize) {
+        let runs = self.runs();
+        let run = runs.get(index).unwrap_or_else(|| {
+            panic!(
+                "Run index {} out of range, the benchmark has {} runs",
+                index,
+                runs.len()
+            )
+        });
+
+        // Runs selected by index are executed concurrently by the submitted jobs,
+        // so each one gets its own subdirectory of the working dir
+        let working_dir = WorkingDir {
+            name: run.working_dir.name.clone(),
+            path: run.working_dir.path.join(&run.base_name),
+        };
+
+        *self = Self {
+            datasets: vec![run.dataset.clone()],
+            working_dirs: vec![working_dir],
+            threads: vec![run.threads],
+            kvalues: vec![run.k],
+            sweep: vec![run.sweep.clone()],
+            tools: vec![run.tool.clone()],
+        };
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const LOCAL: &str = r#"
+[[datasets]]
+name = "small"
+files = ["small.fa"]
+
+[[working-dirs]]
+name = "scratch"
+path = "scratch"
+"#;
+
+    /// Config of a benchmark of one tool with the given sweep table
+    fn config(sweep: &str) -> Config {
+        let local: LocalConfig = toml::from_str(LOCAL).unwrap();
+        let tools: Tools = toml::from_str(
+            r#"
+[[tools]]
+name = "tool"
+path = "tool"
+arguments = "<INPUT_FILES> -o <OUTPUT_FILE>"
+"#,
+        )
+        .unwrap();
+        let benchmarks: BenchmarksConfig = toml::from_str(&format!(
+            r#"
+[[benchmarks]]
+name = "sweep"
+datasets = ["small"]
+tools = ["tool"]
+working-dirs = ["scratch"]
+copy-dataset = false
+kvalues = [31]
+threads = [4]
+max-memory = 8
+min-multiplicity = 1
+size-check-time = 1000
+[benchmarks.sweep]
+{}
+"#,
+            sweep
+        ))
+        .unwrap();
+        Config {
+            tools: tools.tools,
+            datasets: local.datasets,
+            benchmarks: benchmarks.benchmarks,
+            working_dirs: local.working_dirs,
+        }
+    }
+
+    fn matrix(sweep: &str) -> RunMatrix {
+        let config = config(sweep);
+        RunMatrix::new(&config, &config.benchmarks[0], &None, &None, &None)
+    }
+
+    #[test]
+    fn swept_overrides() {
+        let config = config("max-memory = [4, 32]\nmultiplicity = [2]\nmode = [\"fast\"]");
+        let benchmark = &config.benchmarks[0];
+        let matrix = RunMatrix::new(&config, benchmark, &None, &None, &None);
+        let runs = matrix.runs();
+        assert_eq!(runs.len(), 2);
+        assert_eq!(runs[0].max_memory_gb(benchmark), Some(4.0));
+        assert_eq!(runs[1].max_memory_gb(benchmark), Some(32.0));
+        assert_eq!(runs[0].metadata(benchmark).multiplicity, 2);
+        assert_eq!(
+            runs[1].base_name,
+            "small_scratch_K31_tool_T4_max-memory=32_mode=fast_multiplicity=2"
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "Swept parameter max_memory must be written max-memory")]
+    fn misspelled_override() {
+        matrix("max_memory = [4, 32]");
+    }
+
+    #[test]
+    #[should_panic(expected = "Swept parameter threads conflicts with <THREADS>")]
+    fn placeholder_conflict() {
+        matrix("threads = [1, 2]");
+    }
+
+    #[test]
+    #[should_panic(expected = "Swept parameters PRESET and preset are both exposed as <PRESET>")]
+    fn same_placeholder() {
+        matrix("preset = [1]\nPRESET = [2]");
+    }
+
+    #[test]
+    #[should_panic(
+        expected = "Values 'a/b' and 'a-b' of swept parameter mode give the same run names"
+    )]
+    fn run_name_collision() {
+        matrix("mode = [\"a/b\", \"a-b\"]");
+    }
+
+    #[test]
+    #[should_panic(expected = "Invalid value 'lots' for swept parameter max-memory")]
+    fn invalid_override_value() {
+        matrix("max-memory = [\"lots\"]");
+    }
+}
@@ -0,0 +1,211 @@
+use crate::dir_cleanup::remove_dirs_on_panic;
+use crate::stats::{get_process_tree_pids, read_all_stats};
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::os::raw::c_int;
+use std::os::unix::raw::pid_t;
+use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
+use std::time::{Duration, Instant};
+
+/// Time given to the tool to exit after SIGTERM, before it is killed
+const TERMINATION_GRACE_PERIOD: Duration = Duration::from_secs(10);
+/// Time given to the processes signalled together with the tool to finish exiting
+const SIGNALLED_EXIT_GRACE_PERIOD: Duration = Duration::from_secs(2);
+
+/// Pid of the tool being benchmarked, zero when no tool is running
+static RUNNING_TOOL: AtomicU32 = AtomicU32::new(0);
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+
+/// Sends a signal to the whole tree of a benchmarked tool.
+/// Tools are spawned as leaders of their own session and process group, so the group id is the tool pid.
+/// Descendants that moved to another group are reached through their ppid links.
+pub fn kill_process_tree(pid: u32, signal: c_int) {
+    let descendants = get_process_tree_pids(pid);
+    unsafe {
+        libc::kill(-(pid as pid_t), signal);
+        for descendant in descendants {
+            libc::kill(descendant as pid_t, signal);
+        }
+    }
+}
+
+/// Process of a tool still alive after the tool exited
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct OrphanProcess {
+    pub pid: u32,
+    pub ppid: u32,
+    pub command: String,
+}
+
+/// Makes the harness adopt the orphaned descendants of the tools instead of init,
+/// so that they can still be found after the tool exits
+pub fn become_child_subreaper() {
+    unsafe {
+        libc::prctl(libc::PR_SET_CHILD_SUBREAPER, 1, 0, 0, 0);
+    }
+}
+
+fn process_command(pid: i32, comm: &str) -> String {
+    std::fs::read(format!("/proc/{}/cmdline", pid))
+        .ok()
+        .map(|cmdline| {
+            String::from_utf8_lossy(&cmdline)
+                .trim_end_matches('\0')
+                .replace('\0', " ")
+        })
+        .filter(|cmdline| !cmdline.is_empty())
+        .unwrap_or_else(|| comm.to_string())
+}
+
+/// Live processes left by a tool that exited: the ones in its session or process group,
+/// and the ones adopted by the harness with their descendants
+pub fn find_orphan_processes(tool_pid: u32) -> Vec<OrphanProcess> {
+    let harness_pid = std::process::id();
+    let adopted: HashSet<_> = get_process_tree_pids(harness_pid).into_iter().collect();
+    let tool_pid = tool_pid as i32;
+
+    read_all_stats()
+        .into_iter()
+        .filter(|stat| {
+            stat.pid != harness_pid as i32
+                && stat.pid != tool_pid
+                && stat.state != 'Z'
+                && (stat.session == tool_pid
+                    || stat.pgrp == tool_pid
+                    || adopted.contains(&(stat.pid as u32)))
+        })
+        .map(|stat| OrphanProcess {
+            pid: stat.pid as u32,
+            ppid: stat.ppid as u32,
+            command: process_command(stat.pid, &stat.comm),
+        })
+        .collect()
+}
+
+/// Processes surviving a tool. If its tree was signalled by the harness, the processes that received
+/// the same signal are given a short time to exit, so that only the ones still alive are reported
+pub fn find_surviving_processes(tool_pid: u32, signalled: bool) -> Vec<OrphanProcess> {
+    let start = Instant::now();
+    loop {
+        reap_adopted_zombies();
+        let orphans = find_orphan_processes(tool_pid);
+        if orphans.is_empty() || !signalled || start.elapsed() > SIGNALLED_EXIT_GRACE_PERIOD {
+            return orphans;
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+/// Reaps the adopted processes that already exited
+fn reap_adopted_zombies() {
+    let harness_pid = std::process::id() as i32;
+    for stat in read_all_stats() {
+        if stat.ppid == harness_pid && stat.state == 'Z' {
+            unsafe {
+                libc::waitpid(stat.pid, std::ptr::null_mut(), libc::WNOHANG);
+            }
+        }
+    }
+}
+
+/// Kills the orphans of a tool, also the ones they fork while being killed, and reaps the adopted ones
+pub fn kill_orphan_processes(tool_pid: u32) {
+    let start = Instant::now();
+    loop {
+        reap_adopted_zombies();
+        let orphans = find_orphan_processes(tool_pid);
+        if orphans.is_empty() {
+            break;
+        }
+        if start.elapsed() > TERMINATION_GRACE_PERIOD {
+            println!(
+                "WARNING: Cannot kill {} orphan processes of the tool!",
+                orphans.len()
+            );
+            break;
+        }
+        for orphan in orphans {
+            unsafe {
+                libc::kill(orphan.pid as pid_t, libc::SIGKILL);
+            }
+        }
+        std::thread::sleep(Duration::from_millis(10));
+    }
+}
+
+/// Returns the conventional name of a signal number, e.g. SIGSEGV
+pub fn signal_name(signal: c_int) -> String {
+    match signal {
+        libc::SIGHUP => "SIGHUP",
+        libc::SIGINT => "SIGINT",
+        libc::SIGQUIT => "SIGQUIT",
+        libc::SIGILL => "SIGILL",
+        libc::SIGTRAP => "SIGTRAP",
+        libc::SIGABRT => "SIGABRT",
+        libc::SIGBUS => "SIGBUS",
+        libc::SIGFPE => "SIGFPE",
+        libc::SIGKILL => "SIGKILL",
+        libc::SIGUSR1 => "SIGUSR1",
+        libc::SIGSEGV => "SIGSEGV",
+        libc::SIGUSR2 => "SIGUSR2",
+        libc::SIGPIPE => "SIGPIPE",
+        libc::SIGALRM => "SIGALRM",
+        libc::SIGTERM => "SIGTERM",
+        libc::SIGXCPU => "SIGXCPU",
+        libc::SIGXFSZ => "SIGXFSZ",
+        libc::SIGSYS => "SIGSYS",
+        _ => return format!("SIG{}", signal),
+    }
+    .to_string()
+}
+
+pub fn is_interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
+
+/// Registers the spawned tool, killing it right away if the benchmark was interrupted while spawning it
+pub fn set_running_tool(pid: u32) {
+    RUNNING_TOOL.store(pid, Ordering::SeqCst);
+    if is_interrupted() {
+        kill_process_tree(pid, libc::SIGKILL);
+    }
+}
+
+/// Must be called before the tool is reaped, so that its pid cannot be reused by another process
+pub fn clear_running_tool() {
+    RUNNING_TOOL.store(0, Ordering::SeqCst);
+}
+
+/// Ctrl+C handler, stops the running tool and lets the benchmark record the interrupted run.
+/// A second Ctrl+C kills the tool and aborts immediately
+pub fn interrupt() {
+    let pid = RUNNING_TOOL.load(Ordering::SeqCst);
+
+    if INTERRUPTED.swap(true, Ordering::SeqCst) {
+        println!("Ctrl+C pressed again, aborting!");
+        if pid != 0 {
+            kill_process_tree(pid, libc::SIGKILL);
+        }
+        remove_dirs_on_panic();
+        std::process::exit(130);
+    }
+
+    println!("Ctrl+C pressed, stopping the benchmark (press again to abort)");
+    if pid == 0 {
+        return;
+    }
+
+    kill_process_tree(pid, libc::SIGTERM);
+    let start = Instant::now();
+    while RUNNING_TOOL.load(Ordering::SeqCst) == pid {
+        if start.elapsed() > TERMINATION_GRACE_PERIOD {
+            println!(
+                "Tool still running {:?} after SIGTERM, killing it!",
+                TERMINATION_GRACE_PERIOD
+            );
+            kill_process_tree(pid, libc::SIGKILL);
+            break;
+        }
+        std::thread::sleep(Duration::from_millis(100));
+    }
+}
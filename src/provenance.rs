@@ -0,0 +1,198 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+const VERSION_TIMEOUT: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct HostInfo {
+    pub hostname: String,
+    pub cpu_model: Option<String>,
+    pub cpu_sockets: usize,
+    pub cpu_cores: usize,
+    pub cpu_threads: usize,
+    pub memory_gb: f64,
+    pub kernel: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct FilesystemInfo {
+    pub mount_point: String,
+    pub fs_type: String,
+    pub source: String,
+    /// Whether the backing block device is rotational, None for virtual filesystems
+    pub rotational: Option<bool>,
+}
+
+/// Where and with what a run was produced
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct Provenance {
+    pub host: HostInfo,
+    pub working_dir_filesystem: Option<FilesystemInfo>,
+    /// RFC 3339 timestamps of the start and of the end of the tool
+    pub start_time: String,
+    pub end_time: String,
+    /// Output of the tool invoked with its version-arguments
+    pub tool_version: Option<String>,
+}
+
+fn read_trimmed(path: impl AsRef<Path>) -> Option<String> {
+    std::fs::read_to_string(path)
+        .ok()
+        .map(|content| content.trim().to_string())
+}
+
+pub fn host_info() -> HostInfo {
+    let cpuinfo = std::fs::read_to_string("/proc/cpuinfo").unwrap_or_default();
+
+    let mut cpu_model = None;
+    let mut cpu_threads = 0;
+    let mut sockets = HashSet::new();
+    let mut cores = HashSet::new();
+    let mut physical_id = None;
+
+    for line in cpuinfo.lines() {
+        let mut parts = line.splitn(2, ':').map(|part| part.trim());
+        match (parts.next(), parts.next()) {
+            (Some("processor"), _) => cpu_threads += 1,
+            (Some("model name"), Some(model)) if cpu_model.is_none() => {
+                cpu_model = Some(model.to_string())
+            }
+            (Some("physical id"), Some(id)) => {
+                sockets.insert(id.to_string());
+                physical_id = Some(id.to_string());
+            }
+            (Some("core id"), Some(id)) => {
+                cores.insert((physical_id.clone(), id.to_string()));
+            }
+            _ => {}
+        }
+    }
+
+    let memory_kb: u64 = std::fs::read_to_string("/proc/meminfo")
+        .unwrap_or_default()
+        .lines()
+        .find_map(|line| line.strip_prefix("MemTotal:"))
+        .and_then(|value| value.trim().trim_end_matches("kB").trim().parse().ok())
+        .unwrap_or(0);
+
+    HostInfo {
+        hostname: read_trimmed("/proc/sys/kernel/hostname").unwrap_or_default(),
+        cpu_model,
+        cpu_sockets: sockets.len().max(1),
+        // Some architectures do not report the core ids
+        cpu_cores: if cores.is_empty() {
+            cpu_threads
+        } else {
+            cores.len()
+        },
+        cpu_threads,
+        memory_gb: memory_kb as f64 / (1024.0 * 1024.0),
+        kernel: read_trimmed("/proc/sys/kernel/osrelease").unwrap_or_default(),
+    }
+}
+
+/// Decodes the octal escapes used by mountinfo for spaces and other special characters
+fn unescape_mount_path(path: &str) -> String {
+    let mut result = String::new();
+    let mut chars = path.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c == '\\' {
+            let code: String = (0..3).filter_map(|_| chars.next()).collect();
+            match u8::from_str_radix(&code, 8) {
+                Ok(byte) => result.push(byte as char),
+                Err(_) => {
+                    result.push(c);
+                    result.push_str(&code);
+                }
+            }
+        } else {
+            result.push(c);
+        }
+    }
+    result
+}
+
+fn is_rotational(device: &str) -> Option<bool> {
+    let device_dir = PathBuf::from("/sys/dev/block").join(device);
+    // Partitions have the queue in the directory of their parent disk
+    [
+        device_dir.join("queue/rotational"),
+        device_dir.join("../queue/rotational"),
+    ]
+    .iter()
+    .find_map(|path| read_trimmed(path))
+    .map(|value| value == "1")
+}
+
+/// Filesystem of the mount containing `path`, found in /proc/self/mountinfo
+pub fn filesystem_info(path: &Path) -> Option<FilesystemInfo> {
+    let path = path.canonicalize().ok()?;
+    let mountinfo = std::fs::read_to_string("/proc/self/mountinfo").ok()?;
+
+    let mut best: Option<(PathBuf, FilesystemInfo)> = None;
+    for line in mountinfo.lines() {
+        let (mount, fs) = match line.split_once(" - ") {
+            Some(parts) => parts,
+            None => continue,
+        };
+        let mount_fields: Vec<_> = mount.split_whitespace().collect();
+        let fs_fields: Vec<_> = fs.split_whitespace().collect();
+        if mount_fields.len() < 5 || fs_fields.len() < 2 {
+            continue;
+        }
+
+        let mount_point = PathBuf::from(unescape_mount_path(mount_fields[4]));
+        let is_longer = best.as_ref().map_or(true, |(best, _)| {
+            mount_point.as_os_str().len() >= best.as_os_str().len()
+        });
+
+        if path.starts_with(&mount_point) && is_longer {
+            let device = mount_fields[2];
+            best = Some((
+                mount_point.clone(),
+                FilesystemInfo {
+                    mount_point: mount_point.to_string_lossy().into_owned(),
+                    fs_type: fs_fields[0].to_string(),
+                    source: fs_fields[1].to_string(),
+                    rotational: if device.starts_with("0:") {
+                        None
+                    } else {
+                        is_rotational(device)
+                    },
+                },
+            ));
+        }
+    }
+    best.map(|(_, info)| info)
+}
+
+/// Runs the version command of a tool, returning its trimmed stdout and stderr
+pub fn tool_version(mut command: Command) -> Option<String> {
+    let mut child = command
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .ok()?;
+
+    let start = Instant::now();
+    while child.try_wait().ok()?.is_none() {
+        if start.elapsed() > VERSION_TIMEOUT {
+            let _ = child.kill();
+            let _ = child.wait();
+            return None;
+        }
+        std::thread::sleep(Duration::from_millis(50));
+    }
+
+    let output = child.wait_with_output().ok()?;
+    let text = format!(
+        "{}{}",
+        String::from_utf8_lossy(&output.stdout),
+        String::from_utf8_lossy(&output.stderr)
+    );
+    Some(text.trim().to_string())
+}
@@ -0,0 +1,134 @@
+use sha2::{Digest, Sha256};
+use std::fs::{create_dir_all, read_dir, File};
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+
+/// Conditions under which a combination with existing results is run again
+#[derive(Default)]
+pub struct RerunPolicy {
+    pub force: bool,
+    pub failed: bool,
+    pub older_than: Option<SystemTime>,
+    pub tool_changed: bool,
+}
+
+/// Accepts a date as YYYY-MM-DD, in local time, or as an RFC 3339 timestamp
+pub fn parse_date(date: &str) -> Result<SystemTime, String> {
+    if let Ok(timestamp) = chrono::DateTime::parse_from_rfc3339(date) {
+        return Ok(timestamp.into());
+    }
+    let day = chrono::NaiveDate::parse_from_str(date, "%Y-%m-%d")
+        .map_err(|_| format!("Invalid date '{}', expected YYYY-MM-DD or RFC 3339", date))?;
+    let midnight = day
+        .and_hms_opt(0, 0, 0)
+        .unwrap()
+        .and_local_timezone(chrono::Local)
+        .earliest()
+        .ok_or(format!("Invalid local date '{}'", date))?;
+    Ok(midnight.into())
+}
+
+pub fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut hasher = Sha256::new();
+    io::copy(&mut File::open(path)?, &mut hasher)?;
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Completion of a stored run, also for results written by older versions
+fn has_completed(results: &serde_json::Value) -> bool {
+    if let Some(outcome) = results.get("outcome") {
+        outcome.as_str() == Some("completed")
+    } else if let Some(completed) = results.get("has_completed") {
+        completed.as_bool() == Some(true)
+    } else {
+        // Memory search results, an interrupted search is resumed from scratch
+        results.get("interrupted").and_then(|i| i.as_bool()) != Some(true)
+            && results
+                .get("min_successful_memory_gb")
+                .map_or(false, |memory| !memory.is_null())
+    }
+}
+
+impl RerunPolicy {
+    /// Returns the reason to run again a combination whose results file exists, None to skip it
+    pub fn rerun_reason(&self, results_file: &Path, tool_path: &Path) -> Option<String> {
+        if self.force {
+            return Some("forced".to_string());
+        }
+
+        if self.failed || self.tool_changed {
+            let results: serde_json::Value = File::open(results_file)
+                .ok()
+                .and_then(|file| serde_json::from_reader(file).ok())
+                .unwrap_or(serde_json::Value::Null);
+
+            if self.failed && !has_completed(&results) {
+                return Some("previous run did not complete".to_string());
+            }
+
+            if self.tool_changed {
+                let stored_hash = results.get("tool_sha256").and_then(|h| h.as_str());
+                let current_hash = sha256_file(tool_path).ok();
+                match (stored_hash, current_hash) {
+                    (None, _) => return Some("no tool hash recorded".to_string()),
+                    (Some(stored), Some(current)) if stored != current => {
+                        return Some("tool binary changed".to_string())
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        if let Some(date) = self.older_than {
+            let modified = results_file.metadata().and_then(|m| m.modified()).ok()?;
+            if modified < date {
+                return Some("results older than the given date".to_string());
+            }
+        }
+
+        None
+    }
+}
+
+/// Moves all the files of a run, results, traces, logs and outputs, to `archive_dir`,
+/// keeping the same layout of the results path
+pub fn archive_run_files(results_path: &Path, archive_dir: &Path, base_name: &str) {
+    let prefixes = [
+        ("results-dir", vec![format!("{}thr", base_name)]),
+        (
+            "logs-dir",
+            vec![format!("{}.", base_name), format!("{}-", base_name)],
+        ),
+        ("outputs-dir", vec![format!("{}thr_out", base_name)]),
+    ];
+
+    for (dir, prefixes) in prefixes {
+        let entries = match read_dir(results_path.join(dir)) {
+            Ok(entries) => entries,
+            Err(_) => continue,
+        };
+        let files: Vec<PathBuf> = entries
+            .filter_map(|entry| entry.ok())
+            .filter(|entry| {
+                let name = entry.file_name();
+                let name = name.to_string_lossy();
+                prefixes
+                    .iter()
+                    .any(|prefix| name.starts_with(prefix.as_str()))
+            })
+            .map(|entry| entry.path())
+            .collect();
+
+        if files.is_empty() {
+            continue;
+        }
+
+        let dest_dir = archive_dir.join(dir);
+        create_dir_all(&dest_dir).unwrap();
+        for file in files {
+            std::fs::rename(&file, dest_dir.join(file.file_name().unwrap()))
+                .unwrap_or_else(|err| panic!("Cannot archive {}: {}", file.display(), err));
+        }
+    }
+}
@@ -0,0 +1,457 @@
+use crate::disk_usage::DiskSamplerStats;
+use crate::memory_search::MemorySearchResults;
+use crate::provenance::Provenance;
+use crate::runner::{RunMetadata, RunOutcome, RunResults, RESULTS_SCHEMA_VERSION};
+use crate::stats::ProcessIo;
+use crate::write_json;
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::BTreeMap;
+use std::fs::{create_dir_all, File};
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+use walkdir::WalkDir;
+
+#[derive(StructOpt)]
+pub struct MigrateResultsCli {
+    /// Results path of a benchmark, containing the results-dir directory
+    results_path: PathBuf,
+    /// Directory where the original files are copied, defaults to schema-backup/<date> in the results path
+    #[structopt(long)]
+    backup_dir: Option<PathBuf>,
+}
+
+/// Results written before the schema version was introduced
+const LEGACY_SCHEMA_VERSION: u32 = 1;
+
+/// Values of the fields missing in the results written by older versions
+fn default_results() -> RunResults {
+    RunResults {
+        schema_version: RESULTS_SCHEMA_VERSION,
+        metadata: RunMetadata::default(),
+        command_line: String::new(),
+        full_command_line: String::new(),
+        working_dir: None,
+        environment: BTreeMap::new(),
+        tool_sha256: None,
+        provenance: Provenance::default(),
+        max_memory_gb: 0.0,
+        max_measured_memory_gb: 0.0,
+        max_measured_tree_memory_gb: 0.0,
+        measured_user_time_secs: 0.0,
+        measured_system_time_secs: 0.0,
+        measured_tree_user_time_secs: 0.0,
+        measured_tree_system_time_secs: 0.0,
+        user_time_secs: 0.0,
+        system_time_secs: 0.0,
+        real_time_secs: 0.0,
+        total_written_gb: 0.0,
+        total_read_gb: 0.0,
+        tree_io: ProcessIo::default(),
+        max_used_disk_gb: 0.0,
+        output_file_sizes: vec![],
+        outputs: vec![],
+        outcome: RunOutcome::Failed,
+        exit_code: None,
+        signal: None,
+        core_dumped: false,
+        memory_limit_gb: None,
+        address_space_limit_gb: None,
+        disk_limit_gb: None,
+        memory_kill_gb: None,
+        memory_kill_rss_gb: None,
+        cgroup_memory_peak_gb: None,
+        cgroup_oom_kills: None,
+        cache_control: None,
+        orphan_processes: vec![],
+        disk_sampler: DiskSamplerStats::default(),
+        summary: None,
+    }
+}
+
+/// Base name of the run that produced a results file, also for the repetitions in the thr-reps directories
+fn run_base_name(file: &Path) -> Option<String> {
+    let file_name = file.file_name()?.to_str()?;
+    ["thr-info.json", "thr-memsearch.json"]
+        .iter()
+        .find_map(|suffix| file_name.strip_suffix(suffix))
+        .or_else(|| {
+            file.parent()?
+                .file_name()?
+                .to_str()?
+                .strip_suffix("thr-reps")
+        })
+        .map(|name| name.to_string())
+}
+
+/// Infers the metadata from a base name {dataset}_{wdir}_K{k}_{tool}_T{threads}[_query][_{name}={value}...].
+/// Underscores are ambiguous, they are assumed to be part of the dataset and tool names
+pub fn metadata_from_base_name(base_name: &str) -> Option<RunMetadata> {
+    let parts: Vec<_> = base_name.split('_').collect();
+    let number =
+        |part: &str, prefix: char| -> Option<usize> { part.strip_prefix(prefix)?.parse().ok() };
+
+    let k_index = (2..parts.len()).find(|&i| number(parts[i], 'K').is_some())?;
+    let threads_index = (k_index + 2..parts.len()).find(|&i| number(parts[i], 'T').is_some())?;
+    let k = number(parts[k_index], 'K')?;
+    let tool = parts[k_index + 1..threads_index].join("_");
+
+    let mut query = false;
+    let mut sweep = BTreeMap::new();
+    for part in &parts[threads_index + 1..] {
+        match part.split_once('=') {
+            Some((name, value)) => {
+                sweep.insert(name.to_string(), value.to_string());
+            }
+            None if *part == "query" => query = true,
+            None => return None,
+        }
+    }
+
+    // Variants of the same tool used to be merged by their name suffix
+    let tool_group = tool.strip_suffix("-ref").unwrap_or(&tool);
+    let tool_group = tool_group.strip_suffix("-reads").unwrap_or(tool_group);
+    let tool_group = tool_group
+        .strip_suffix(&format!("-k{}", k))
+        .unwrap_or(tool_group)
+        .to_string();
+
+    Some(RunMetadata {
+        benchmark: String::new(),
+        dataset: parts[..k_index - 1].join("_"),
+        working_dir: parts[k_index - 1].to_string(),
+        k,
+        tool,
+        tool_group,
+        threads: number(parts[threads_index], 'T')?,
+        multiplicity: sweep
+            .get("multiplicity")
+            .and_then(|m| m.parse().ok())
+            .unwrap_or(0),
+        query,
+        sweep,
+    })
+}
+
+pub fn schema_version(results: &Value) -> u32 {
+    results
+        .get("schema_version")
+        .and_then(|version| version.as_u64())
+        .map_or(LEGACY_SCHEMA_VERSION, |version| version as u32)
+}
+
+/// Converts a legacy results file, either of a run or of a memory search, to the layout of version 2
+fn upgrade_legacy(file: &Path, mut results: Map<String, Value>) -> Map<String, Value> {
+    if !results.contains_key("metadata") {
+        let mut metadata = run_base_name(file)
+            .and_then(|base_name| metadata_from_base_name(&base_name))
+            .unwrap_or_else(|| panic!("Cannot infer the run metadata of {}", file.display()));
+        // Swept values stored in the results are not mangled like in the file name
+        if let Some(sweep) = results.remove("sweep") {
+            metadata.sweep = serde_json::from_value(sweep).unwrap();
+        }
+        results.insert(
+            "metadata".to_string(),
+            serde_json::to_value(metadata).unwrap(),
+        );
+    }
+
+    if !results.contains_key("probes") {
+        if !results.contains_key("outcome") {
+            let completed = results
+                .get("has_completed")
+                .and_then(|completed| completed.as_bool())
+                .unwrap_or(false);
+            let outcome = if completed {
+                RunOutcome::Completed
+            } else {
+                RunOutcome::Failed
+            };
+            results.insert(
+                "outcome".to_string(),
+                serde_json::to_value(outcome).unwrap(),
+            );
+        }
+        results.remove("has_completed");
+
+        // Before the tree memory was measured the sampled memory was stored in MiB
+        if !results.contains_key("max_measured_tree_memory_gb") {
+            if let Some(memory) = results
+                .get("max_measured_memory_gb")
+                .and_then(|memory| memory.as_f64())
+            {
+                results.insert(
+                    "max_measured_memory_gb".to_string(),
+                    Value::from(memory / 1024.0),
+                );
+            }
+        }
+    }
+    results
+}
+
+/// Upgrades the results to the current schema, the file name is used to infer the metadata of legacy files
+pub fn upgrade_results(file: &Path, results: Value) -> Value {
+    let version = schema_version(&results);
+    if version > RESULTS_SCHEMA_VERSION {
+        panic!(
+            "Results file {} has schema version {}, newer than the supported {}",
+            file.display(),
+            version,
+            RESULTS_SCHEMA_VERSION
+        );
+    }
+
+    let mut results = match results {
+        Value::Object(results) => results,
+        _ => panic!("Results file {} is not a JSON object", file.display()),
+    };
+    if version == LEGACY_SCHEMA_VERSION {
+        results = upgrade_legacy(file, results);
+    }
+
+    if version < RESULTS_SCHEMA_VERSION {
+        // Fields added by the later versions take their default values
+        if !results.contains_key("probes") {
+            let defaults = match serde_json::to_value(default_results()).unwrap() {
+                Value::Object(defaults) => defaults,
+                _ => unreachable!(),
+            };
+            for (field, value) in defaults {
+                results.entry(field).or_insert(value);
+            }
+        }
+        results.insert(
+            "schema_version".to_string(),
+            Value::from(RESULTS_SCHEMA_VERSION),
+        );
+    }
+    Value::Object(results)
+}
+
+fn load<T: DeserializeOwned>(file: &Path) -> T {
+    let results: Value = serde_json::from_reader(File::open(file).unwrap())
+        .unwrap_or_else(|err| panic!("Cannot read results file {}: {}", file.display(), err));
+    serde_json::from_value(upgrade_results(file, results))
+        .unwrap_or_else(|err| panic!("Invalid results file {}: {}", file.display(), err))
+}
+
+pub fn load_results(file: &Path) -> RunResults {
+    load(file)
+}
+
+/// Replaces a results file with its upgraded content, after saving the original in `backup_file`.
+/// The content is deserialized before touching the original file, and to keep the usual order of the fields
+fn rewrite<T: DeserializeOwned + Serialize>(file: &Path, upgraded: Value, backup_file: &Path) {
+    let results: T = serde_json::from_value(upgraded)
+        .unwrap_or_else(|err| panic!("Cannot migrate {}: {}", file.display(), err));
+
+    create_dir_all(backup_file.parent().unwrap()).unwrap();
+    std::fs::copy(file, backup_file).unwrap_or_else(|err| {
+        panic!(
+            "Cannot backup {} to {}: {}",
+            file.display(),
+            backup_file.display(),
+            err
+        )
+    });
+
+    write_json(file, &results);
+}
+
+pub fn migrate_results(args: MigrateResultsCli) {
+    let results_dir = args.results_path.join("results-dir");
+    let backup_dir = args.backup_dir.unwrap_or_else(|| {
+        args.results_path
+            .join("schema-backup")
+            .join(chrono::Local::now().format("%Y%m%d-%H%M%S").to_string())
+    });
+
+    let mut migrated = 0;
+    let mut up_to_date = 0;
+
+    for entry in WalkDir::new(&results_dir).sort_by_file_name() {
+        let entry = entry.unwrap();
+        let file = entry.path();
+        if !entry.file_type().is_file() || file.extension().map_or(true, |ext| ext != "json") {
+            continue;
+        }
+
+        let results: Value = serde_json::from_reader(File::open(file).unwrap())
+            .unwrap_or_else(|err| panic!("Cannot read results file {}: {}", file.display(), err));
+        if schema_version(&results) == RESULTS_SCHEMA_VERSION {
+            up_to_date += 1;
+            continue;
+        }
+
+        let backup_file = backup_dir.join(file.strip_prefix(&args.results_path).unwrap());
+        let upgraded = upgrade_results(file, results);
+        if upgraded.get("probes").is_some() {
+            rewrite::<MemorySearchResults>(file, upgraded, &backup_file);
+        } else {
+            rewrite::<RunResults>(file, upgraded, &backup_file);
+        }
+        println!("Migrated {}", file.display());
+        migrated += 1;
+    }
+
+    println!(
+        "Migrated {} results files to schema version {}, {} already up to date",
+        migrated, RESULTS_SCHEMA_VERSION, up_to_date
+    );
+    if migrated > 0 {
+        println!("Original files saved in {}", backup_dir.display());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Results paths written by each schema version, v1-baseline by the first version of the benchmark
+    const VERSIONS: [&str; 6] = ["v1-baseline", "v1", "v2", "v3", "v4", "v5"];
+    const BASE_NAME: &str = "small_set_ssd_K31_my_tool_T4";
+
+    fn fixture(version: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("test-data/results-schema")
+            .join(version)
+    }
+
+    fn run_file(results_path: &Path) -> PathBuf {
+        results_path
+            .join("results-dir")
+            .join(format!("{}thr-info.json", BASE_NAME))
+    }
+
+    fn memsearch_file(results_path: &Path) -> PathBuf {
+        results_path
+            .join("results-dir")
+            .join(format!("{}thr-memsearch.json", BASE_NAME))
+    }
+
+    fn read_value(file: &Path) -> Value {
+        serde_json::from_reader(File::open(file).unwrap()).unwrap()
+    }
+
+    fn check_metadata(metadata: &RunMetadata) {
+        assert_eq!(metadata.dataset, "small_set");
+        assert_eq!(metadata.working_dir, "ssd");
+        assert_eq!(metadata.k, 31);
+        assert_eq!(metadata.tool, "my_tool");
+        assert_eq!(metadata.threads, 4);
+        assert!(!metadata.query);
+    }
+
+    #[test]
+    fn metadata_from_base_names() {
+        check_metadata(&metadata_from_base_name(BASE_NAME).unwrap());
+
+        let metadata =
+            metadata_from_base_name("human_ram_K63_bifrost-k63_T16_query_multiplicity=2").unwrap();
+        assert_eq!(metadata.dataset, "human");
+        assert_eq!(metadata.tool, "bifrost-k63");
+        assert_eq!(metadata.tool_group, "bifrost");
+        assert_eq!(metadata.multiplicity, 2);
+        assert!(metadata.query);
+        assert_eq!(metadata.sweep["multiplicity"], "2");
+
+        assert!(metadata_from_base_name("no-k-value_T4").is_none());
+        assert!(metadata_from_base_name("d_wd_K31_tool_T4_unexpected").is_none());
+    }
+
+    #[test]
+    fn upgrade_legacy_files() {
+        // The first version only recorded the completion, later legacy files also have the outcome
+        // and stored the sampled memory in MiB
+        for (version, has_outcome, measured_memory_gb) in [
+            ("v1-baseline", false, 3.19140625 / 1024.0),
+            ("v1", true, 0.000125885009765625),
+        ] {
+            let file = run_file(&fixture(version));
+            let results = read_value(&file);
+            assert_eq!(schema_version(&results), LEGACY_SCHEMA_VERSION);
+            assert_eq!(results.get("outcome").is_some(), has_outcome);
+            assert_eq!(results.get("has_completed").is_some(), !has_outcome);
+
+            let upgraded = upgrade_legacy(&file, results.as_object().unwrap().clone());
+            assert!(upgraded.get("has_completed").is_none());
+            assert_eq!(upgraded["outcome"], "completed");
+            assert_eq!(upgraded["max_measured_memory_gb"], measured_memory_gb);
+            check_metadata(&serde_json::from_value(upgraded["metadata"].clone()).unwrap());
+        }
+
+        let file = memsearch_file(&fixture("v1"));
+        let upgraded = upgrade_legacy(&file, read_value(&file).as_object().unwrap().clone());
+        assert!(upgraded.get("outcome").is_none());
+        check_metadata(&serde_json::from_value(upgraded["metadata"].clone()).unwrap());
+    }
+
+    #[test]
+    fn load_every_schema_version() {
+        for version in VERSIONS {
+            let results = load_results(&run_file(&fixture(version)));
+            assert_eq!(
+                results.schema_version, RESULTS_SCHEMA_VERSION,
+                "{}",
+                version
+            );
+            assert_eq!(results.outcome, RunOutcome::Completed, "{}", version);
+            check_metadata(&results.metadata);
+        }
+
+        for version in ["v1", "v2"] {
+            let results: MemorySearchResults = load(&memsearch_file(&fixture(version)));
+            assert_eq!(results.schema_version, RESULTS_SCHEMA_VERSION);
+            assert_eq!(results.min_successful_memory_gb, Some(0.5));
+            check_metadata(&results.metadata);
+        }
+    }
+
+    #[test]
+    fn migrate_every_schema_version() {
+        let dir = std::env::temp_dir().join(format!("migrate-results-{}", std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        create_dir_all(&dir).unwrap();
+
+        for version in VERSIONS {
+            fs_extra::dir::copy(fixture(version), &dir, &fs_extra::dir::CopyOptions::new())
+                .unwrap();
+            let results_path = dir.join(version);
+            let backup_dir = dir.join(format!("{}-backup", version));
+
+            let mut files = vec![run_file(&results_path)];
+            if memsearch_file(&results_path).exists() {
+                files.push(memsearch_file(&results_path));
+            }
+
+            migrate_results(MigrateResultsCli {
+                results_path: results_path.clone(),
+                backup_dir: Some(backup_dir.clone()),
+            });
+
+            for file in &files {
+                let relative = file.strip_prefix(&results_path).unwrap();
+                let original = std::fs::read(fixture(version).join(relative)).unwrap();
+                let backup = std::fs::read(backup_dir.join(relative)).unwrap();
+                assert_eq!(backup, original, "{}", version);
+
+                let migrated = read_value(file);
+                assert_eq!(
+                    schema_version(&migrated),
+                    RESULTS_SCHEMA_VERSION,
+                    "{}",
+                    version
+                );
+                assert!(migrated.get("has_completed").is_none());
+            }
+            assert_eq!(
+                load_results(&run_file(&results_path)).outcome,
+                RunOutcome::Completed
+            );
+        }
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -1,17 +1,36 @@
-use crate::config::{Dataset, Tool};
+use crate::cgroup::{MemoryCgroup, DEFAULT_CGROUP_ROOT};
+use crate::config::{CacheState, Dataset, DiskUsageMethod, Tool};
+use crate::disk_usage::{DiskSampler, DiskSamplerStats};
 use fork::Fork;
-use rlimit::Resource;
-
-use crate::stats::get_process_info;
+use rlimit::{Resource, Rlim};
+
+use crate::outputs::{
+    canonicalizable_output, default_output_specs, detect_outputs, has_required_outputs,
+    DetectedOutput, OutputPattern,
+};
+use crate::page_cache::{apply_cache_state, CacheControl};
+use crate::plan::{resolve_path, sweep_variable_name, SWEEP_OVERRIDES};
+use crate::process::{
+    become_child_subreaper, clear_running_tool, find_surviving_processes, is_interrupted,
+    kill_orphan_processes, kill_process_tree, set_running_tool, signal_name, OrphanProcess,
+};
+use crate::provenance::{filesystem_info, host_info, tool_version, Provenance};
+use crate::rerun::sha256_file;
+use crate::stats::{
+    get_process_info, get_process_io, get_process_tree_info, get_process_tree_io, ProcessIo,
+};
+use crate::summary::RunsSummary;
+use crate::template::{render_arguments, shell_quote};
 use cgroups_rs::cgroup_builder::*;
 use cgroups_rs::*;
 use serde::{Deserialize, Serialize};
 use std::cmp::min;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs::File;
-use std::io::{BufRead, BufReader, Write};
+use std::io::{BufRead, BufReader, BufWriter, Write};
 use std::mem::MaybeUninit;
 use std::os::raw::c_int;
+use std::os::unix::process::CommandExt;
 use std::os::unix::raw::pid_t;
 use std::path::{Path, PathBuf};
 use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
@@ -30,50 +49,450 @@ pub struct Parameters {
     pub output_file: String,
     pub canonical_file: String,
     pub temp_dir: String,
+    pub input_files_list: PathBuf,
     pub log_file: PathBuf,
+    pub trace_file: Option<PathBuf>,
     pub memory_gb: Option<f64>,
     pub size_check_time: Duration,
     pub query_files: (Option<String>, Option<String>),
+    pub timeout: Option<Duration>,
+    pub memory_limit_gb: Option<f64>,
+    pub address_space_limit_gb: Option<f64>,
+    pub disk_limit_gb: Option<f64>,
+    pub memory_kill_gb: Option<f64>,
+    pub cgroup_root: Option<PathBuf>,
+    pub cache_state: Option<CacheState>,
+    /// Outputs of the previous run, evicted from the page cache with the cold cache state
+    pub previous_outputs: Vec<PathBuf>,
+    pub disk_usage_method: Option<DiskUsageMethod>,
+    pub metadata: RunMetadata,
+}
+
+/// Version of the results files format, increased when fields are added or changed.
+/// 2 added the run metadata, 3 the orphan processes, 4 the disk sampler statistics,
+/// 5 the disk limit, 6 the memory watchdog
+pub const RESULTS_SCHEMA_VERSION: u32 = 6;
+
+/// Benchmark combination that produced a results file
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RunMetadata {
+    pub benchmark: String,
+    pub dataset: String,
+    pub working_dir: String,
+    pub k: usize,
+    pub tool: String,
+    /// Column of the tool in the tables, the tool name if not grouped
+    pub tool_group: String,
+    pub threads: usize,
+    /// Zero if unknown, for results migrated from the legacy layout
+    pub multiplicity: usize,
+    pub query: bool,
+    /// Values of the swept parameters of the benchmark
+    pub sweep: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum RunOutcome {
+    Completed,
+    Failed,
+    TimedOut,
+    KilledBySignal,
+    Interrupted,
+    OutOfMemory,
+    /// Killed for exceeding the disk limit of the benchmark
+    DiskLimit,
+    /// Killed by the RSS watchdog for exceeding memory-kill-gb
+    MemoryLimit,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
 pub struct RunResults {
+    pub schema_version: u32,
+    pub metadata: RunMetadata,
     pub command_line: String,
+    /// Command line including the launcher of the tool
+    pub full_command_line: String,
+    pub working_dir: Option<String>,
+    pub environment: BTreeMap<String, String>,
+    /// Hash of the tool binary, used to rerun the benchmarks of updated tools
+    pub tool_sha256: Option<String>,
+    /// Host, filesystem and tool version the run was measured with
+    pub provenance: Provenance,
     pub max_memory_gb: f64,
     pub max_measured_memory_gb: f64,
+    pub max_measured_tree_memory_gb: f64,
+    pub measured_user_time_secs: f64,
+    pub measured_system_time_secs: f64,
+    pub measured_tree_user_time_secs: f64,
+    pub measured_tree_system_time_secs: f64,
     pub user_time_secs: f64,
     pub system_time_secs: f64,
     pub real_time_secs: f64,
+    /// Block I/O from rusage of the direct child, misses page cache reads and tmpfs
     pub total_written_gb: f64,
     pub total_read_gb: f64,
+    /// I/O counters from /proc/<pid>/io summed over the process tree
+    pub tree_io: ProcessIo,
     pub max_used_disk_gb: f64,
     pub output_file_sizes: Vec<(String, (u64, f64))>,
-    pub has_completed: bool,
+    /// Outputs matched by the output spec of the tool
+    pub outputs: Vec<DetectedOutput>,
+    pub outcome: RunOutcome,
+    pub exit_code: Option<i32>,
+    pub signal: Option<String>,
+    pub core_dumped: bool,
+    pub memory_limit_gb: Option<f64>,
+    pub address_space_limit_gb: Option<f64>,
+    pub disk_limit_gb: Option<f64>,
+    pub memory_kill_gb: Option<f64>,
+    /// Tree-wide RSS sampled when the watchdog killed the tool
+    pub memory_kill_rss_gb: Option<f64>,
+    pub cgroup_memory_peak_gb: Option<f64>,
+    pub cgroup_oom_kills: Option<u64>,
+    pub cache_control: Option<CacheControl>,
+    /// Processes of the tool still alive after it exited, killed before the next run
+    pub orphan_processes: Vec<OrphanProcess>,
+    pub disk_sampler: DiskSamplerStats,
+    pub summary: Option<RunsSummary>,
+}
+
+impl RunResults {
+    /// Numeric metrics of the run, summarized when a benchmark is repeated
+    pub fn metrics(&self) -> Vec<(&'static str, f64)> {
+        let mut metrics = vec![
+            ("max_memory_gb", self.max_memory_gb),
+            ("max_measured_memory_gb", self.max_measured_memory_gb),
+            (
+                "max_measured_tree_memory_gb",
+                self.max_measured_tree_memory_gb,
+            ),
+            ("measured_user_time_secs", self.measured_user_time_secs),
+            ("measured_system_time_secs", self.measured_system_time_secs),
+            (
+                "measured_tree_user_time_secs",
+                self.measured_tree_user_time_secs,
+            ),
+            (
+                "measured_tree_system_time_secs",
+                self.measured_tree_system_time_secs,
+            ),
+            ("user_time_secs", self.user_time_secs),
+            ("system_time_secs", self.system_time_secs),
+            ("real_time_secs", self.real_time_secs),
+            ("total_written_gb", self.total_written_gb),
+            ("total_read_gb", self.total_read_gb),
+            (
+                "tree_io_rchar_gb",
+                self.tree_io.rchar as f64 / (1024.0 * 1024.0 * 1024.0),
+            ),
+            (
+                "tree_io_wchar_gb",
+                self.tree_io.wchar as f64 / (1024.0 * 1024.0 * 1024.0),
+            ),
+            (
+                "tree_io_read_gb",
+                self.tree_io.read_bytes as f64 / (1024.0 * 1024.0 * 1024.0),
+            ),
+            (
+                "tree_io_write_gb",
+                self.tree_io.write_bytes as f64 / (1024.0 * 1024.0 * 1024.0),
+            ),
+            (
+                "tree_io_cancelled_write_gb",
+                self.tree_io.cancelled_write_bytes as f64 / (1024.0 * 1024.0 * 1024.0),
+            ),
+            ("max_used_disk_gb", self.max_used_disk_gb),
+        ];
+
+        if let Some(peak) = self.cgroup_memory_peak_gb {
+            metrics.push(("cgroup_memory_peak_gb", peak));
+        }
+
+        metrics
+    }
 }
 
 fn absolute_path(path: impl AsRef<Path>) -> io::Result<PathBuf> {
-    let path = path.as_ref();
+    Ok(resolve_path(&env::current_dir()?, path.as_ref()))
+}
 
-    let absolute_path = if path.is_absolute() {
-        path.to_path_buf()
-    } else {
-        env::current_dir()?.join(path)
+/// Placeholders of the templates set by the benchmark, the swept parameters cannot reuse them
+pub const TEMPLATE_PLACEHOLDERS: &[&str] = &[
+    "THREADS",
+    "KVALUE",
+    "MULTIPLICITY",
+    "INPUT_FILES",
+    "INPUT_FILES_LIST",
+    "INPUT_FILES_READS",
+    "INPUT_FILES_SEQUENCES",
+    "OUTPUT_FILE",
+    "TEMP_DIR",
+    "MAX_MEMORY",
+    "INPUT_GRAPH",
+    "INPUT_QUERY",
+    "INPUT_COLORS",
+];
+
+/// Variables available to the arguments templates of the tools
+fn template_variables(
+    tool: &Tool,
+    parameters: &Parameters,
+    input_files: &[String],
+) -> HashMap<String, Vec<String>> {
+    // Legacy prefixes, the same can now be obtained with conditionals in the template
+    let reads_prefix = tool
+        .reads_arg_prefix
+        .clone()
+        .filter(|_| parameters.multiplicity > 1);
+    let sequences_prefix = tool
+        .sequences_arg_prefix
+        .clone()
+        .filter(|_| parameters.multiplicity == 1);
+
+    let prefixed_files = |prefix: &Option<String>| -> Vec<String> {
+        match prefix {
+            Some(prefix) => input_files
+                .iter()
+                .map(|x| vec![prefix.clone(), x.clone()])
+                .flatten()
+                .collect(),
+            None => vec![],
+        }
     };
 
-    Ok(absolute_path)
+    let mut variables = [
+        ("THREADS", vec![parameters.max_threads.to_string()]),
+        ("KVALUE", vec![parameters.k.to_string()]),
+        ("MULTIPLICITY", vec![parameters.multiplicity.to_string()]),
+        ("INPUT_FILES", input_files.to_vec()),
+        ("INPUT_FILES_LIST", {
+            let mut vec = vec![];
+
+            if tool.use_prefix_for_list.unwrap_or(false) {
+                let prefix = if parameters.multiplicity > 1 {
+                    tool.reads_arg_prefix.clone()
+                } else {
+                    tool.sequences_arg_prefix.clone()
+                };
+                vec.extend(prefix);
+            }
+
+            vec.push(parameters.input_files_list.to_str().unwrap().to_string());
+            vec
+        }),
+        ("INPUT_FILES_READS", prefixed_files(&reads_prefix)),
+        ("INPUT_FILES_SEQUENCES", prefixed_files(&sequences_prefix)),
+        (
+            "OUTPUT_FILE",
+            vec![absolute_path(&parameters.output_file)
+                .unwrap()
+                .into_os_string()
+                .into_string()
+                .unwrap()],
+        ),
+        (
+            "TEMP_DIR",
+            vec![absolute_path(&parameters.temp_dir)
+                .unwrap()
+                .into_os_string()
+                .into_string()
+                .unwrap()],
+        ),
+        (
+            "MAX_MEMORY",
+            vec![format!("{:.2}", parameters.memory_gb.unwrap_or(0.0))],
+        ),
+        ("INPUT_GRAPH", input_files.to_vec()),
+        (
+            "INPUT_QUERY",
+            vec![parameters.query_files.0.clone().unwrap_or(String::new())],
+        ),
+        (
+            "INPUT_COLORS",
+            vec![parameters.query_files.1.clone().unwrap_or(String::new())],
+        ),
+    ]
+    .into_iter()
+    .map(|(name, values)| (name.to_string(), values))
+    .collect::<HashMap<_, _>>();
+
+    debug_assert!(variables
+        .keys()
+        .all(|name| TEMPLATE_PLACEHOLDERS.contains(&name.as_str())));
+
+    // Validated by RunMatrix::new, the overrides are already applied to the parameters
+    for (name, value) in &parameters.metadata.sweep {
+        if !SWEEP_OVERRIDES.contains(&name.as_str()) {
+            variables.insert(sweep_variable_name(name), vec![value.clone()]);
+        }
+    }
+    variables
 }
 
-fn get_dir_size(path: impl AsRef<Path>) -> u64 {
-    let mut dir_size = 0;
-    for entry in WalkDir::new(path) {
-        if let Ok(file) = entry {
-            dir_size += file.metadata().map(|m| m.len()).unwrap_or(0)
+/// Fully resolved invocation of a tool
+pub struct ToolCommand {
+    pub tool_path: PathBuf,
+    pub arguments: Vec<String>,
+    /// Command prefixed to the tool, e.g. numactl or taskset
+    pub launcher: Vec<String>,
+    /// Variables added to the inherited environment
+    pub environment: BTreeMap<String, String>,
+    pub working_dir: Option<PathBuf>,
+    /// Glob patterns of the files written by the tool
+    pub outputs: Vec<OutputPattern>,
+    pub version_arguments: Option<Vec<String>>,
+}
+
+fn quote_command_line<'a>(arguments: impl Iterator<Item = &'a str>) -> String {
+    arguments.map(shell_quote).collect::<Vec<_>>().join(" ")
+}
+
+impl ToolCommand {
+    /// Command line of the tool alone, without the launcher
+    pub fn command_line(&self) -> String {
+        quote_command_line(
+            std::iter::once(self.tool_path.to_str().unwrap())
+                .chain(self.arguments.iter().map(|a| a.as_str())),
+        )
+    }
+
+    pub fn full_command_line(&self) -> String {
+        quote_command_line(
+            self.launcher
+                .iter()
+                .map(|a| a.as_str())
+                .chain(std::iter::once(self.tool_path.to_str().unwrap()))
+                .chain(self.arguments.iter().map(|a| a.as_str())),
+        )
+    }
+
+    /// Environment seen by the tool, the inherited one with the tool variables applied
+    pub fn effective_environment(&self) -> BTreeMap<String, String> {
+        let mut environment: BTreeMap<_, _> = env::vars().collect();
+        environment.extend(self.environment.clone());
+        environment
+    }
+
+    pub fn to_command(&self) -> std::process::Command {
+        let mut command = match self.launcher.split_first() {
+            Some((launcher, launcher_args)) => {
+                let mut command = std::process::Command::new(launcher);
+                command.args(launcher_args).arg(&self.tool_path);
+                command
+            }
+            None => std::process::Command::new(&self.tool_path),
+        };
+        command.args(&self.arguments).envs(&self.environment);
+        if let Some(working_dir) = &self.working_dir {
+            command.current_dir(working_dir);
+        }
+        command
+    }
+
+    /// Command printing the version of the tool, run without the launcher
+    pub fn version_command(&self) -> Option<std::process::Command> {
+        let arguments = self.version_arguments.as_ref()?;
+        let mut command = std::process::Command::new(&self.tool_path);
+        command.args(arguments).envs(&self.environment);
+        if let Some(working_dir) = &self.working_dir {
+            command.current_dir(working_dir);
         }
+        Some(command)
     }
-    dir_size
 }
 
 impl Runner {
+    pub fn tool_path(base_dir: impl AsRef<Path>, tool: &Tool) -> PathBuf {
+        resolve_path(base_dir.as_ref(), &tool.path)
+    }
+
+    /// Resolves the path of the tool and renders its arguments, launcher and environment templates
+    pub fn build_command(
+        base_dir: impl AsRef<Path>,
+        tool: &Tool,
+        input_files: &Vec<PathBuf>,
+        parameters: &Parameters,
+    ) -> ToolCommand {
+        let input_files_string = input_files
+            .iter()
+            .map(|f| f.as_os_str().to_str().unwrap().to_string())
+            .collect::<Vec<String>>();
+
+        let variables = template_variables(tool, parameters, &input_files_string);
+        let render_with = |template: &str, variables: &HashMap<String, Vec<String>>| {
+            render_arguments(template, variables).unwrap_or_else(|err| {
+                panic!(
+                    "Invalid template '{}' for tool {}: {}",
+                    template, tool.name, err
+                )
+            })
+        };
+        let render = |template: &str| render_with(template, &variables);
+
+        // Paths substituted in the output patterns must not be interpreted as globs
+        let glob_variables = variables
+            .iter()
+            .map(|(name, values)| {
+                (
+                    name.clone(),
+                    values.iter().map(|v| glob::Pattern::escape(v)).collect(),
+                )
+            })
+            .collect();
+        let output_dir = absolute_path(Path::new(&parameters.output_file).parent().unwrap())
+            .unwrap()
+            .into_os_string()
+            .into_string()
+            .unwrap();
+        let outputs = tool
+            .outputs
+            .clone()
+            .unwrap_or_else(default_output_specs)
+            .into_iter()
+            .map(|spec| {
+                let pattern = match render_with(&spec.pattern, &glob_variables).as_slice() {
+                    [pattern] => pattern.clone(),
+                    _ => panic!(
+                        "Output pattern '{}' of tool {} must be a single path",
+                        spec.pattern, tool.name
+                    ),
+                };
+                OutputPattern {
+                    pattern: if Path::new(&pattern).is_absolute() {
+                        pattern
+                    } else {
+                        format!("{}/{}", glob::Pattern::escape(&output_dir), pattern)
+                    },
+                    format: spec.format,
+                    primary: spec.primary.unwrap_or(false),
+                }
+            })
+            .collect();
+
+        ToolCommand {
+            tool_path: Self::tool_path(&base_dir, tool),
+            arguments: render(&tool.arguments),
+            launcher: tool
+                .launcher
+                .as_ref()
+                .map(|launcher| render(launcher))
+                .unwrap_or_default(),
+            environment: tool
+                .env
+                .iter()
+                .flatten()
+                .map(|(name, value)| (name.clone(), render(value).join(" ")))
+                .collect(),
+            working_dir: tool
+                .cwd
+                .as_ref()
+                .map(|cwd| resolve_path(base_dir.as_ref(), Path::new(&render(cwd).join(" ")))),
+            outputs,
+            version_arguments: tool.version_arguments.as_ref().map(|args| render(args)),
+        }
+    }
+
     pub fn run_tool(
         base_dir: impl AsRef<Path>,
         tool: Tool,
@@ -106,130 +525,40 @@ impl Runner {
                 .build(hier)
         };
 
-        let input_files_list_file_name =
-            std::env::temp_dir().join(format!("input-files-{}.txt", dataset_name));
         {
-            let mut input_files_list = File::create(&input_files_list_file_name).unwrap();
+            let mut input_files_list = File::create(&parameters.input_files_list).unwrap();
             input_files_list.write_all(input_files_string.join("\n").as_bytes());
             input_files_list.write_all(b"\n");
         }
 
-        let program_arguments: HashMap<&str, Vec<String>> = [
-            ("<THREADS>", vec![parameters.max_threads.to_string()]),
-            ("<KVALUE>", vec![parameters.k.to_string()]),
-            ("<MULTIPLICITY>", vec![parameters.multiplicity.to_string()]),
-            ("<INPUT_FILES>", input_files_string.clone()),
-            ("<INPUT_FILES_LIST>", {
-                let mut vec = vec![];
-
-                if tool.use_prefix_for_list.unwrap_or(false) {
-                    if parameters.multiplicity > 1 {
-                        if let Some(reads_prefix) = tool.reads_arg_prefix.clone() {
-                            vec.push(reads_prefix)
-                        }
-                    } else {
-                        if let Some(sequences_prefix) = tool.sequences_arg_prefix.clone() {
-                            vec.push(sequences_prefix)
-                        }
-                    }
-                }
-
-                vec.push(input_files_list_file_name.to_str().unwrap().to_string());
-                vec
-            }),
-            ("<INPUT_FILES_READS>", {
-                if let Some(reads_prefix) = tool.reads_arg_prefix {
-                    if parameters.multiplicity > 1 {
-                        input_files_string
-                            .iter()
-                            .map(|x| vec![reads_prefix.clone(), x.clone()])
-                            .flatten()
-                            .collect()
-                    } else {
-                        vec![]
-                    }
-                } else {
-                    vec![]
-                }
-            }),
-            ("<INPUT_FILES_SEQUENCES>", {
-                if let Some(sequences_prefix) = tool.sequences_arg_prefix {
-                    if parameters.multiplicity == 1 {
-                        input_files_string
-                            .iter()
-                            .map(|x| vec![sequences_prefix.clone(), x.clone()])
-                            .flatten()
-                            .collect()
-                    } else {
-                        vec![]
-                    }
-                } else {
-                    vec![]
-                }
-            }),
-            (
-                "<OUTPUT_FILE>",
-                vec![absolute_path(&parameters.output_file)
-                    .unwrap()
-                    .into_os_string()
-                    .into_string()
-                    .unwrap()],
-            ),
-            (
-                "<TEMP_DIR>",
-                vec![absolute_path(&parameters.temp_dir)
-                    .unwrap()
-                    .into_os_string()
-                    .into_string()
-                    .unwrap()],
-            ),
-            (
-                "<MAX_MEMORY>",
-                vec![format!("{:.2}", parameters.memory_gb.unwrap_or(0.0))],
-            ),
-            ("<INPUT_FILES>", input_files_string.clone()),
-            ("<INPUT_GRAPH>", input_files_string.clone()),
-            (
-                "<INPUT_QUERY>",
-                vec![parameters.query_files.0.clone().unwrap_or(String::new())],
-            ),
-            (
-                "<INPUT_COLORS>",
-                vec![parameters.query_files.1.clone().unwrap_or(String::new())],
-            ),
-        ]
-        .iter()
-        .cloned()
-        .collect();
-
-        let tool_path = if tool.path.is_absolute() {
-            tool.path
-        } else {
-            base_dir.as_ref().join(tool.path).to_path_buf()
-        };
-
-        let mut arguments = tool.arguments.split(" ").collect::<Vec<_>>();
+        let tool_command = Self::build_command(&base_dir, &tool, input_files, &parameters);
+
+        let cache_control = parameters.cache_state.map(|state| {
+            // The working dir holds the intermediates of the previous runs and the copied dataset
+            let working_dir = Path::new(&parameters.output_file)
+                .parent()
+                .unwrap()
+                .parent()
+                .unwrap();
+            let mut previous_outputs = parameters.previous_outputs.clone();
+            previous_outputs.push(working_dir.to_path_buf());
+            previous_outputs.push(PathBuf::from(&parameters.temp_dir));
+            apply_cache_state(state, input_files, &previous_outputs)
+        });
 
-        let mut i = 0;
-        while i < arguments.len() {
-            if program_arguments.contains_key(arguments[i]) {
-                let args = &program_arguments[arguments[i]];
-                arguments.remove(i);
-                for (j, arg) in args.iter().enumerate() {
-                    arguments.insert(i + j, arg);
-                }
-            } else {
-                i += 1;
-            }
-        }
+        let host = host_info();
+        let working_dir_filesystem =
+            filesystem_info(Path::new(&parameters.output_file).parent().unwrap());
+        let tool_version = tool_command.version_command().and_then(tool_version);
 
+        let start_timestamp = chrono::Local::now().to_rfc3339();
         let start_time = Instant::now();
 
         println!(
             "Running tool {} with dataset {} K = {} threads = {}",
             &tool.name, &dataset_name, parameters.k, parameters.max_threads
         );
-        eprintln!("{} {}", tool_path.display(), arguments.join(" "));
+        eprintln!("{}", tool_command.full_command_line());
 
         // Reset the max_rss for the current process
         {
@@ -243,45 +572,227 @@ impl Runner {
                 .unwrap_or(());
         }
 
-        let mut command = std::process::Command::new(&tool_path)
-            .args(arguments.as_slice())
+        let memory_cgroup = parameters.memory_limit_gb.map(|limit| {
+            MemoryCgroup::create(
+                parameters
+                    .cgroup_root
+                    .clone()
+                    .unwrap_or(PathBuf::from(DEFAULT_CGROUP_ROOT)),
+                &format!("run-{}", std::process::id()),
+                (limit * 1024.0 * 1024.0 * 1024.0) as u64,
+            )
+            .expect("Cannot create the memory cgroup, please set cgroup-root to a writable cgroup v2 directory")
+        });
+
+        let mut command = tool_command.to_command();
+        command
             .stdout(File::create(&parameters.log_file).unwrap())
-            .stderr(File::create(parameters.log_file.with_extension("stderr")).unwrap())
-            .spawn()
-            .unwrap();
+            .stderr(File::create(parameters.log_file.with_extension("stderr")).unwrap());
+
+        // A new session isolates the tool from the terminal and identifies all its processes,
+        // also the ones that start a new process group
+        become_child_subreaper();
+        unsafe {
+            command.pre_exec(|| {
+                if libc::setsid() < 0 {
+                    return Err(io::Error::last_os_error());
+                }
+                Ok(())
+            });
+        }
+
+        if let Some(memory_cgroup) = &memory_cgroup {
+            let procs_fd = memory_cgroup.procs_fd();
+            // Join the cgroup before exec, so that no allocation of the tool escapes the limit
+            unsafe {
+                command.pre_exec(move || {
+                    if libc::write(procs_fd, b"0".as_ptr() as *const libc::c_void, 1) < 0 {
+                        return Err(io::Error::last_os_error());
+                    }
+                    Ok(())
+                });
+            }
+        }
+
+        if let Some(limit) = parameters.address_space_limit_gb {
+            let limit = Rlim::from_raw((limit * 1024.0 * 1024.0 * 1024.0) as _);
+            unsafe {
+                command.pre_exec(move || Resource::AS.set(limit, limit));
+            }
+        }
+
+        let out_dir = Path::new(&parameters.output_file).parent().unwrap();
+        let disk_usage_method = parameters.disk_usage_method.unwrap_or_else(|| {
+            // A dedicated filesystem is mounted on the working dir
+            let is_dedicated = working_dir_filesystem.as_ref().map_or(false, |fs| {
+                out_dir.parent().unwrap().canonicalize().ok()
+                    == Some(PathBuf::from(&fs.mount_point))
+            });
+            if is_dedicated {
+                DiskUsageMethod::Statvfs
+            } else {
+                DiskUsageMethod::Inotify
+            }
+        });
+        let mut disk_sampler =
+            DiskSampler::new(disk_usage_method, Path::new(&parameters.temp_dir), out_dir);
+
+        let mut command = command.spawn().unwrap();
+        set_running_tool(command.id());
 
         let is_finished = Arc::new(AtomicBool::new(false));
+        let timed_out = Arc::new(AtomicBool::new(false));
+        let disk_limit_exceeded = Arc::new(AtomicBool::new(false));
+        // Tree RSS of the sample that exceeded memory-kill-gb, zero if not exceeded
+        let memory_kill_rss = Arc::new(AtomicU64::new(0));
 
         let pid = command.id();
 
         let is_finished_thr = is_finished.clone();
-        let temp_dir_thr = parameters.temp_dir.clone();
-        let out_dir_thr = PathBuf::from(&parameters.output_file)
-            .parent()
-            .unwrap()
-            .to_path_buf();
-        let out_dir_for_final_size = out_dir_thr.clone();
+        let out_dir_for_final_size = out_dir.to_path_buf();
 
         let maximum_disk_usage = Arc::new(AtomicU64::new(0));
         let maximum_rss_usage = Arc::new(AtomicU64::new(0));
+        let maximum_tree_rss_usage = Arc::new(AtomicU64::new(0));
+        // Cpu times in microseconds, as (user, system) for the child and for the whole tree
+        let measured_cpu_times = Arc::new([
+            AtomicU64::new(0),
+            AtomicU64::new(0),
+            AtomicU64::new(0),
+            AtomicU64::new(0),
+        ]);
 
         let maximum_disk_usage_thr = maximum_disk_usage.clone();
         let maximum_rss_usage_thr = maximum_rss_usage.clone();
+        let maximum_tree_rss_usage_thr = maximum_tree_rss_usage.clone();
+        let measured_cpu_times_thr = measured_cpu_times.clone();
+        let timed_out_thr = timed_out.clone();
+        let disk_limit_exceeded_thr = disk_limit_exceeded.clone();
+        let memory_kill_rss_thr = memory_kill_rss.clone();
+
+        // Maximum over the samples of each counter of the tree, they only decrease when a process
+        // exits before its parent waits for it
+        let measured_tree_io = Arc::new(parking_lot::Mutex::new(ProcessIo::default()));
+        let measured_tree_io_thr = measured_tree_io.clone();
+        let update_tree_io = |measured: &parking_lot::Mutex<ProcessIo>, io: ProcessIo| {
+            let mut measured = measured.lock();
+            measured.rchar = measured.rchar.max(io.rchar);
+            measured.wchar = measured.wchar.max(io.wchar);
+            measured.read_bytes = measured.read_bytes.max(io.read_bytes);
+            measured.write_bytes = measured.write_bytes.max(io.write_bytes);
+            measured.cancelled_write_bytes =
+                measured.cancelled_write_bytes.max(io.cancelled_write_bytes);
+        };
+
+        let mut trace_writer = parameters.trace_file.as_ref().map(|trace_file| {
+            let mut writer = BufWriter::new(File::create(trace_file).unwrap());
+            writeln!(
+                writer,
+                "time_secs,rss_bytes,temp_dir_bytes,output_dir_bytes,cpu_user_secs,cpu_system_secs,read_bytes,write_bytes,tree_rss_bytes,tree_cpu_user_secs,tree_cpu_system_secs,tree_rchar,tree_wchar,tree_read_bytes,tree_write_bytes,disk_used_bytes"
+            )
+            .unwrap();
+            writer
+        });
 
         let maximum_disk_usage_thread = std::thread::spawn(move || {
             while !is_finished_thr.load(Ordering::Relaxed) {
-                maximum_disk_usage_thr.fetch_max(
-                    get_dir_size(&temp_dir_thr) + get_dir_size(&out_dir_thr),
-                    Ordering::Relaxed,
-                );
+                if let Some(timeout) = parameters.timeout {
+                    if start_time.elapsed() > timeout && !timed_out_thr.load(Ordering::Relaxed) {
+                        println!("Timeout of {:?} expired, killing the tool!", timeout);
+                        timed_out_thr.store(true, Ordering::Relaxed);
+                        kill_process_tree(pid, libc::SIGKILL);
+                    }
+                }
+                let disk_usage = disk_sampler.sample();
+                let process_info = get_process_info(pid).ok();
+                let tree_info = get_process_tree_info(pid).ok();
+                let process_io = get_process_io(pid).unwrap_or_default();
+                let tree_io = get_process_tree_io(pid).unwrap_or_default();
+                update_tree_io(&measured_tree_io_thr, tree_io);
+
+                maximum_disk_usage_thr.fetch_max(disk_usage.total_bytes, Ordering::Relaxed);
+                let tree_rss = tree_info.map(|x| x.memory_usage_bytes).unwrap_or(0);
+                if let Some(limit) = parameters.memory_kill_gb {
+                    if tree_rss as f64 > limit * 1024.0 * 1024.0 * 1024.0
+                        && memory_kill_rss_thr.load(Ordering::Relaxed) == 0
+                    {
+                        println!(
+                            "Memory usage of {:.2}GB over the limit of {:.2}GB, killing the tool!",
+                            tree_rss as f64 / (1024.0 * 1024.0 * 1024.0),
+                            limit
+                        );
+                        memory_kill_rss_thr.store(tree_rss, Ordering::Relaxed);
+                        kill_process_tree(pid, libc::SIGKILL);
+                    }
+                }
+                if let Some(limit) = parameters.disk_limit_gb {
+                    if disk_usage.total_bytes as f64 > limit * 1024.0 * 1024.0 * 1024.0
+                        && !disk_limit_exceeded_thr.load(Ordering::Relaxed)
+                    {
+                        println!("Disk limit of {:.2}GB exceeded, killing the tool!", limit);
+                        disk_limit_exceeded_thr.store(true, Ordering::Relaxed);
+                        kill_process_tree(pid, libc::SIGKILL);
+                    }
+                }
                 maximum_rss_usage_thr.fetch_max(
-                    get_process_info(pid)
-                        .map(|x| x.memory_usage_bytes)
-                        .unwrap_or(0),
+                    process_info.map(|x| x.memory_usage_bytes).unwrap_or(0),
                     Ordering::Relaxed,
                 );
+                maximum_tree_rss_usage_thr.fetch_max(tree_rss, Ordering::Relaxed);
+                for (counter, time) in measured_cpu_times_thr.iter().zip([
+                    process_info.map(|x| x.cpu_time_user),
+                    process_info.map(|x| x.cpu_time_kernel),
+                    tree_info.map(|x| x.cpu_time_user),
+                    tree_info.map(|x| x.cpu_time_kernel),
+                ]) {
+                    counter.fetch_max(
+                        time.map(|t| t.as_micros() as u64).unwrap_or(0),
+                        Ordering::Relaxed,
+                    );
+                }
+
+                if let Some(writer) = &mut trace_writer {
+                    // Flush every sample to keep the trace of runs that bring down the harness
+                    writeln!(
+                        writer,
+                        "{:.3},{},{},{},{:.3},{:.3},{},{},{},{:.3},{:.3},{},{},{},{},{}",
+                        start_time.elapsed().as_secs_f64(),
+                        process_info.map(|x| x.memory_usage_bytes).unwrap_or(0),
+                        // Empty when the method does not measure the directories separately
+                        disk_usage
+                            .temp_dir_bytes
+                            .map_or(String::new(), |bytes| bytes.to_string()),
+                        disk_usage
+                            .output_dir_bytes
+                            .map_or(String::new(), |bytes| bytes.to_string()),
+                        process_info
+                            .map(|x| x.cpu_time_user.as_secs_f64())
+                            .unwrap_or(0.0),
+                        process_info
+                            .map(|x| x.cpu_time_kernel.as_secs_f64())
+                            .unwrap_or(0.0),
+                        process_io.read_bytes,
+                        process_io.write_bytes,
+                        tree_info.map(|x| x.memory_usage_bytes).unwrap_or(0),
+                        tree_info
+                            .map(|x| x.cpu_time_user.as_secs_f64())
+                            .unwrap_or(0.0),
+                        tree_info
+                            .map(|x| x.cpu_time_kernel.as_secs_f64())
+                            .unwrap_or(0.0),
+                        tree_io.rchar,
+                        tree_io.wchar,
+                        tree_io.read_bytes,
+                        tree_io.write_bytes,
+                        disk_usage.total_bytes,
+                    )
+                    .and_then(|_| writer.flush())
+                    .unwrap();
+                }
+
                 std::thread::sleep(parameters.size_check_time);
             }
+            disk_sampler.finish()
         });
 
         #[cfg(feature = "cpu-limit")]
@@ -289,9 +800,25 @@ impl Runner {
             "Cannot set correct cgroup, please initialize as root with the start subcommand",
         );
 
+        // Wait for the exit without reaping, the counters of the zombie still include all the
+        // children it waited for, and would be lost after the reaping
+        unsafe {
+            let mut info: libc::siginfo_t = MaybeUninit::zeroed().assume_init();
+            libc::waitid(
+                libc::P_PID,
+                pid as libc::id_t,
+                &mut info,
+                libc::WEXITED | libc::WNOWAIT,
+            );
+        }
+        clear_running_tool();
+        if let Ok(tree_io) = get_process_tree_io(pid) {
+            update_tree_io(&measured_tree_io, tree_io);
+        }
+
         let mut rusage: libc::rusage;
+        let mut status = 0;
         unsafe {
-            let mut status = 0;
             rusage = MaybeUninit::zeroed().assume_init();
             libc::wait4(
                 command.id() as pid_t,
@@ -302,53 +829,101 @@ impl Runner {
         }
         let total_seconds = start_time.elapsed().as_secs_f64();
 
-        is_finished.store(true, Ordering::Relaxed);
-        maximum_disk_usage_thread.join();
-
-        let mut has_completed = false;
-
-        let output_result = {
-            let output_file = Path::new(&parameters.output_file);
-            let output_parent = output_file.parent().unwrap();
-            let mut result = None;
-            for file in output_parent.read_dir().unwrap() {
-                let entry = file.unwrap();
-                let file_name = entry.file_name().to_str().unwrap().to_string();
-
-                if file_name.starts_with(output_file.file_name().unwrap().to_str().unwrap())
-                    && file_name.ends_with(".gfa")
-                {
-                    // Mark gfa files as completed but do not process them
-                    has_completed = true;
-                }
-
-                if file_name.starts_with(output_file.file_name().unwrap().to_str().unwrap())
-                    && file_name.ends_with(".fa")
-                {
-                    result = Some(entry.path());
-                    break;
-                }
+        let signalled = is_interrupted()
+            || timed_out.load(Ordering::Relaxed)
+            || disk_limit_exceeded.load(Ordering::Relaxed)
+            || memory_kill_rss.load(Ordering::Relaxed) > 0;
+        let orphan_processes = find_surviving_processes(pid, signalled);
+        if !orphan_processes.is_empty() {
+            println!(
+                "WARNING: {} processes of tool {} still running after its exit, killing them!",
+                orphan_processes.len(),
+                tool.name
+            );
+            for orphan in &orphan_processes {
+                println!("  {} {}", orphan.pid, orphan.command);
             }
-            result
+        }
+        kill_orphan_processes(pid);
+        let end_timestamp = chrono::Local::now().to_rfc3339();
+
+        is_finished.store(true, Ordering::Relaxed);
+        let disk_sampler = maximum_disk_usage_thread.join().unwrap();
+
+        let exit_code = libc::WIFEXITED(status).then(|| libc::WEXITSTATUS(status));
+        let signal = libc::WIFSIGNALED(status).then(|| libc::WTERMSIG(status));
+        let core_dumped = signal.is_some() && libc::WCOREDUMP(status);
+
+        let outputs = detect_outputs(&tool_command.outputs);
+        let has_output = has_required_outputs(&tool_command.outputs, &outputs);
+
+        let cgroup_memory_peak = memory_cgroup.as_ref().and_then(|c| c.peak_bytes());
+        let cgroup_oom_kills = memory_cgroup.as_ref().map(|c| c.events().oom_kill);
+        drop(memory_cgroup);
+
+        let outcome = if is_interrupted() && !(exit_code == Some(0) && has_output) {
+            RunOutcome::Interrupted
+        } else if timed_out.load(Ordering::Relaxed) {
+            RunOutcome::TimedOut
+        } else if disk_limit_exceeded.load(Ordering::Relaxed) {
+            RunOutcome::DiskLimit
+        } else if memory_kill_rss.load(Ordering::Relaxed) > 0 {
+            RunOutcome::MemoryLimit
+        } else if cgroup_oom_kills.unwrap_or(0) > 0 && !(exit_code == Some(0) && has_output) {
+            RunOutcome::OutOfMemory
+        } else if signal.is_some() {
+            RunOutcome::KilledBySignal
+        } else if exit_code == Some(0) && has_output {
+            RunOutcome::Completed
+        } else {
+            RunOutcome::Failed
         };
 
-        if let Some(result) = output_result {
-            if parameters.query_files.0.is_none() {
-                canonical_kmers::canonicalize(
-                    &result,
-                    parameters.canonical_file,
+        if outcome == RunOutcome::Completed && parameters.query_files.0.is_none() {
+            match canonicalizable_output(&tool_command.outputs, &outputs) {
+                Some(output) => canonical_kmers::canonicalize(
+                    &output.path,
+                    &parameters.canonical_file,
                     parameters.k,
                     false,
-                );
+                ),
+                None => println!("No fasta output to canonicalize for tool {}", tool.name),
             }
-            has_completed = true;
         }
 
+        let tree_io = *measured_tree_io.lock();
+
         RunResults {
-            command_line: format!("{} {}", tool_path.display(), arguments.join(" ")),
+            schema_version: RESULTS_SCHEMA_VERSION,
+            metadata: parameters.metadata.clone(),
+            command_line: tool_command.command_line(),
+            full_command_line: tool_command.full_command_line(),
+            working_dir: tool_command
+                .working_dir
+                .as_ref()
+                .map(|dir| dir.to_string_lossy().into_owned()),
+            environment: tool_command.effective_environment(),
+            tool_sha256: sha256_file(&tool_command.tool_path).ok(),
+            provenance: Provenance {
+                host,
+                working_dir_filesystem,
+                start_time: start_timestamp,
+                end_time: end_timestamp,
+                tool_version,
+            },
             max_memory_gb: rusage.ru_maxrss as f64 / (1024.0 * 1024.0),
             max_measured_memory_gb: maximum_rss_usage.load(Ordering::Relaxed) as f64
-                / (1024.0 * 1024.0),
+                / (1024.0 * 1024.0 * 1024.0),
+            max_measured_tree_memory_gb: maximum_tree_rss_usage.load(Ordering::Relaxed) as f64
+                / (1024.0 * 1024.0 * 1024.0),
+            measured_user_time_secs: measured_cpu_times[0].load(Ordering::Relaxed) as f64
+                / 1000000.0,
+            measured_system_time_secs: measured_cpu_times[1].load(Ordering::Relaxed) as f64
+                / 1000000.0,
+            measured_tree_user_time_secs: measured_cpu_times[2].load(Ordering::Relaxed) as f64
+                / 1000000.0,
+            measured_tree_system_time_secs: measured_cpu_times[3].load(Ordering::Relaxed) as f64
+                / 1000000.0,
             user_time_secs: rusage.ru_utime.tv_sec as f64
                 + (rusage.ru_utime.tv_usec as f64 / 1000000.0),
             system_time_secs: rusage.ru_stime.tv_sec as f64
@@ -356,6 +931,7 @@ impl Runner {
             real_time_secs: total_seconds,
             total_written_gb: rusage.ru_oublock as f64 / 2048.0 / 1024.0,
             total_read_gb: rusage.ru_inblock as f64 / 2048.0 / 1024.0,
+            tree_io,
             max_used_disk_gb: maximum_disk_usage.load(Ordering::Relaxed) as f64
                 / (1024.0 * 1024.0 * 1024.0),
             output_file_sizes: WalkDir::new(out_dir_for_final_size)
@@ -372,8 +948,26 @@ impl Runner {
                     ))
                 })
                 .collect(),
-
-            has_completed,
+            outputs,
+
+            outcome,
+            exit_code,
+            signal: signal.map(signal_name),
+            core_dumped,
+            memory_limit_gb: parameters.memory_limit_gb,
+            address_space_limit_gb: parameters.address_space_limit_gb,
+            disk_limit_gb: parameters.disk_limit_gb,
+            memory_kill_gb: parameters.memory_kill_gb,
+            memory_kill_rss_gb: Some(memory_kill_rss.load(Ordering::Relaxed))
+                .filter(|&rss| rss > 0)
+                .map(|rss| rss as f64 / (1024.0 * 1024.0 * 1024.0)),
+            cgroup_memory_peak_gb: cgroup_memory_peak
+                .map(|peak| peak as f64 / (1024.0 * 1024.0 * 1024.0)),
+            cgroup_oom_kills,
+            cache_control,
+            orphan_processes,
+            disk_sampler,
+            summary: None,
         }
     }
 }
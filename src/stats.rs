@@ -1,6 +1,7 @@
 // Adapted from simple_process_stats crate (https://github.com/robotty/simple-process-stats)
 
 use procfs::process::Stat;
+use serde::{Deserialize, Serialize};
 use std::fs::File;
 use std::io::{Cursor, Read};
 use std::path::{Path, PathBuf};
@@ -17,10 +18,7 @@ pub struct ProcessStats {
     pub memory_usage_bytes: u64,
 }
 
-pub fn get_process_info(pid: u32) -> Result<ProcessStats, ()> {
-    let bytes_per_page = procfs::page_size().map_err(|_| ())?;
-    let ticks_per_second = procfs::ticks_per_second().map_err(|_| ())?;
-
+fn read_stat(pid: u32) -> Result<Stat, ()> {
     let path = PathBuf::from(format!("/proc/{}/stat", pid));
     let mut file_contents = Vec::new();
     File::open(path)
@@ -29,7 +27,14 @@ pub fn get_process_info(pid: u32) -> Result<ProcessStats, ()> {
         .map_err(|_| ())?;
 
     let readable_string = Cursor::new(file_contents);
-    let stat_file = Stat::from_reader(readable_string).map_err(|_e| ())?;
+    Stat::from_reader(readable_string).map_err(|_e| ())
+}
+
+pub fn get_process_info(pid: u32) -> Result<ProcessStats, ()> {
+    let bytes_per_page = procfs::page_size().map_err(|_| ())?;
+    let ticks_per_second = procfs::ticks_per_second().map_err(|_| ())?;
+
+    let stat_file = read_stat(pid)?;
 
     let memory_usage_bytes = (stat_file.rss as u64) * (bytes_per_page as u64);
     let user_mode_seconds = (stat_file.utime as f64) / (ticks_per_second as f64);
@@ -41,3 +46,125 @@ pub fn get_process_info(pid: u32) -> Result<ProcessStats, ()> {
         memory_usage_bytes,
     })
 }
+
+/// Reads the stat of all the processes in /proc
+pub fn read_all_stats() -> Vec<Stat> {
+    std::fs::read_dir("/proc")
+        .map(|dir| {
+            dir.filter_map(|entry| entry.ok()?.file_name().to_str()?.parse::<u32>().ok())
+                .filter_map(|pid| read_stat(pid).ok())
+                .collect()
+        })
+        .unwrap_or_default()
+}
+
+/// Reads the stat of a process and of all its live descendants, following the ppid links of /proc/*/stat
+fn read_process_tree(pid: u32) -> Vec<Stat> {
+    let mut all_stats = read_all_stats();
+
+    let mut tree = vec![];
+    let mut frontier = vec![pid as i32];
+
+    while let Some(parent) = frontier.pop() {
+        let mut i = 0;
+        while i < all_stats.len() {
+            if all_stats[i].pid == parent || all_stats[i].ppid == parent {
+                let stat = all_stats.swap_remove(i);
+                if stat.pid != parent {
+                    frontier.push(stat.pid);
+                }
+                tree.push(stat);
+            } else {
+                i += 1;
+            }
+        }
+    }
+
+    tree
+}
+
+/// Returns the pids of a process and of all its live descendants
+pub fn get_process_tree_pids(pid: u32) -> Vec<u32> {
+    read_process_tree(pid)
+        .into_iter()
+        .map(|stat| stat.pid as u32)
+        .collect()
+}
+
+/// Sums the statistics over a process and all its live descendants.
+/// The cpu time includes the children already waited for by each process of the tree
+pub fn get_process_tree_info(pid: u32) -> Result<ProcessStats, ()> {
+    let bytes_per_page = procfs::page_size().map_err(|_| ())?;
+    let ticks_per_second = procfs::ticks_per_second().map_err(|_| ())?;
+
+    let tree = read_process_tree(pid);
+    if tree.is_empty() {
+        return Err(());
+    }
+
+    let rss_pages: u64 = tree.iter().map(|stat| stat.rss as u64).sum();
+    let user_ticks: u64 = tree
+        .iter()
+        .map(|stat| stat.utime + stat.cutime.max(0) as u64)
+        .sum();
+    let kernel_ticks: u64 = tree
+        .iter()
+        .map(|stat| stat.stime + stat.cstime.max(0) as u64)
+        .sum();
+
+    Ok(ProcessStats {
+        cpu_time_user: Duration::from_secs_f64(user_ticks as f64 / ticks_per_second as f64),
+        cpu_time_kernel: Duration::from_secs_f64(kernel_ticks as f64 / ticks_per_second as f64),
+        memory_usage_bytes: rss_pages * bytes_per_page as u64,
+    })
+}
+
+/// I/O counters of a process, as reported by /proc/<pid>/io
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct ProcessIo {
+    /// Bytes read by the process, including reads served by the page cache
+    pub rchar: u64,
+    /// Bytes written by the process, including writes still in the page cache
+    pub wchar: u64,
+    /// Bytes actually fetched from the storage layer
+    pub read_bytes: u64,
+    /// Bytes sent to the storage layer
+    pub write_bytes: u64,
+    /// Bytes whose writeback was cancelled, e.g. by truncating dirty pages
+    pub cancelled_write_bytes: u64,
+}
+
+pub fn get_process_io(pid: u32) -> Result<ProcessIo, ()> {
+    let io = procfs::process::Process::new(pid as i32)
+        .map_err(|_| ())?
+        .io()
+        .map_err(|_| ())?;
+
+    Ok(ProcessIo {
+        rchar: io.rchar,
+        wchar: io.wchar,
+        read_bytes: io.read_bytes,
+        write_bytes: io.write_bytes,
+        cancelled_write_bytes: io.cancelled_write_bytes,
+    })
+}
+
+/// Sums the I/O counters over a process and all its live descendants.
+/// The counters of each process include the children it already waited for
+pub fn get_process_tree_io(pid: u32) -> Result<ProcessIo, ()> {
+    let tree = read_process_tree(pid);
+    if tree.is_empty() {
+        return Err(());
+    }
+
+    Ok(tree
+        .iter()
+        .filter_map(|stat| get_process_io(stat.pid as u32).ok())
+        .fold(ProcessIo::default(), |total, io| ProcessIo {
+            rchar: total.rchar + io.rchar,
+            wchar: total.wchar + io.wchar,
+            read_bytes: total.read_bytes + io.read_bytes,
+            write_bytes: total.write_bytes + io.write_bytes,
+            cancelled_write_bytes: total.cancelled_write_bytes + io.cancelled_write_bytes,
+        }))
+}
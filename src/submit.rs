@@ -0,0 +1,450 @@
+use crate::config::Benchmark;
+use crate::plan::{find_benchmark, load_config, resolve_path, PlannedRun, RunMatrix};
+use std::fs::{create_dir_all, File};
+use std::io::Write;
+use std::os::unix::fs::PermissionsExt;
+use std::path::{Path, PathBuf};
+use structopt::StructOpt;
+
+#[derive(StructOpt)]
+pub struct SubmitCli {
+    test_name: String,
+    results_path: PathBuf,
+    #[structopt(short, long, default_value = "config/benchmarks.toml")]
+    benchmarks_config: PathBuf,
+    #[structopt(short, long, default_value = "config/tools.toml")]
+    tools_config: PathBuf,
+    #[structopt(short, long, default_value = "config/local.toml")]
+    env_config: PathBuf,
+    #[structopt(long)]
+    include: Option<String>,
+    #[structopt(long)]
+    exclude: Option<String>,
+    #[structopt(long)]
+    threads: Option<String>,
+    /// Directory where the generated sbatch scripts are written
+    #[structopt(long, default_value = "slurm-jobs")]
+    scripts_dir: PathBuf,
+    /// Generate a single job array instead of one job per run
+    #[structopt(long)]
+    array: bool,
+    /// Submit memory searches instead of benchmarks, the jobs request the upper bound of the search
+    #[structopt(long)]
+    memory_search: bool,
+    /// Submit the generated scripts
+    #[structopt(long)]
+    submit: bool,
+    /// sbatch binary used with --submit
+    #[structopt(long, default_value = "sbatch")]
+    sbatch: PathBuf,
+    #[structopt(long, default_value = "3000:00")]
+    time: String,
+    /// Additional sbatch option added to every script, e.g. --partition=long
+    #[structopt(long)]
+    sbatch_option: Vec<String>,
+}
+
+fn absolute(path: &Path) -> PathBuf {
+    resolve_path(&std::env::current_dir().unwrap(), path)
+}
+
+fn quote(argument: impl AsRef<str>) -> String {
+    crate::template::shell_quote(argument.as_ref())
+}
+
+/// Command line of the bench subcommand, with the same filters so that the run indices match
+fn bench_command_line(args: &SubmitCli, run_index: &str) -> String {
+    let mut command = vec![
+        quote(std::env::current_exe().unwrap().to_str().unwrap()),
+        "bench".to_string(),
+        quote(&args.test_name),
+        quote(absolute(&args.results_path).to_str().unwrap()),
+        "-b".to_string(),
+        quote(absolute(&args.benchmarks_config).to_str().unwrap()),
+        "-t".to_string(),
+        quote(absolute(&args.tools_config).to_str().unwrap()),
+        "-e".to_string(),
+        quote(absolute(&args.env_config).to_str().unwrap()),
+    ];
+    for (option, value) in [
+        ("--include", &args.include),
+        ("--exclude", &args.exclude),
+        ("--threads", &args.threads),
+    ] {
+        if let Some(value) = value {
+            command.push(option.to_string());
+            command.push(quote(value));
+        }
+    }
+    if args.memory_search {
+        command.push("--memory-search".to_string());
+    }
+    command.push("--run-index".to_string());
+    command.push(run_index.to_string());
+    command.join(" ")
+}
+
+/// Memory requested for a run: the largest of its budget, of the memory limits of the benchmark
+/// and of the upper bound of the memory search
+fn job_memory_gb(args: &SubmitCli, benchmark: &Benchmark, run: &PlannedRun) -> Option<f64> {
+    let search_upper_gb = args.memory_search.then(|| {
+        benchmark
+            .memory_search
+            .as_ref()
+            .expect("The benchmark has no memory-search section")
+            .upper_gb
+    });
+
+    [
+        run.max_memory_gb(benchmark),
+        benchmark.memory_limit_gb,
+        benchmark.memory_kill_gb,
+        search_upper_gb,
+    ]
+    .into_iter()
+    .flatten()
+    .reduce(f64::max)
+}
+
+/// Options of the sbatch header, the cpus and memory are taken from the run
+fn job_options(
+    args: &SubmitCli,
+    job_name: &str,
+    output: &Path,
+    cpus: usize,
+    max_memory_gb: Option<f64>,
+) -> Vec<String> {
+    let mut options = vec![
+        format!("--job-name={}", job_name),
+        format!("--output={}", output.display()),
+        "--ntasks=1".to_string(),
+        format!("--cpus-per-task={}", cpus),
+        format!("--time={}", args.time),
+    ];
+    if let Some(memory) = max_memory_gb {
+        options.push(format!("--mem={}", (memory * 1024.0).ceil() as u64));
+    }
+    options.extend(args.sbatch_option.iter().cloned());
+    options
+}
+
+fn write_script(file: &Path, options: &[String], command: &str) {
+    let mut script = File::create(file).unwrap();
+
+    writeln!(script, "#!/bin/bash").unwrap();
+    writeln!(script, "#").unwrap();
+    for option in options {
+        writeln!(script, "#SBATCH {}", option).unwrap();
+    }
+    writeln!(script).unwrap();
+    writeln!(script, "srun {}", command).unwrap();
+
+    let mut perms = script.metadata().unwrap().permissions();
+    perms.set_mode(0o755);
+    std::fs::set_permissions(file, perms).unwrap();
+}
+
+fn sbatch(args: &SubmitCli, script: &Path) {
+    let status = std::process::Command::new(&args.sbatch)
+        .arg(script)
+        .status()
+        .unwrap_or_else(|err| panic!("Cannot run {}: {}", args.sbatch.display(), err));
+    if !status.success() {
+        panic!("Submission of {} failed: {}", script.display(), status);
+    }
+}
+
+pub fn submit(args: SubmitCli) {
+    let (config, _, _) = load_config(
+        &args.benchmarks_config,
+        &args.tools_config,
+        &args.env_config,
+    );
+
+    let benchmark = match find_benchmark(&config.benchmarks, &args.test_name) {
+        Some(benchmark) => benchmark,
+        None => return,
+    };
+
+    let matrix = RunMatrix::new(
+        &config,
+        &benchmark,
+        &args.include,
+        &args.exclude,
+        &args.threads,
+    );
+    let runs = matrix.runs();
+    if runs.is_empty() {
+        println!("No runs to submit for benchmark {}", benchmark.name);
+        return;
+    }
+
+    create_dir_all(&args.scripts_dir).unwrap();
+    let slurm_logs_dir = absolute(&args.results_path).join("slurm-logs");
+    create_dir_all(&slurm_logs_dir).unwrap();
+
+    let scripts: Vec<PathBuf> = if args.array {
+        // All the tasks of an array share the same resources, so the largest run sets them
+        let cpus = runs.iter().map(|run| run.threads).max().unwrap();
+        let memory_gb = runs
+            .iter()
+            .filter_map(|run| job_memory_gb(&args, &benchmark, run))
+            .reduce(f64::max);
+        let script = args
+            .scripts_dir
+            .join(format!("{}-array.sh", benchmark.name));
+        let mut options = job_options(
+            &args,
+            &benchmark.name,
+            &slurm_logs_dir.join("%x-%a.txt"),
+            cpus,
+            memory_gb,
+        );
+        options.push(format!("--array=0-{}", runs.len() - 1));
+        write_script(
+            &script,
+            &options,
+            &bench_command_line(&args, "\"$SLURM_ARRAY_TASK_ID\""),
+        );
+        vec![script]
+    } else {
+        runs.iter()
+            .map(|run| {
+                let script = args
+                    .scripts_dir
+                    .join(format!("{}-{}.sh", run.index, run.base_name));
+                write_script(
+                    &script,
+                    &job_options(
+                        &args,
+                        &format!("{}-{}", benchmark.name, run.base_name),
+                        &slurm_logs_dir.join(format!("{}.txt", run.base_name)),
+                        run.threads,
+                        job_memory_gb(&args, &benchmark, run),
+                    ),
+                    &bench_command_line(&args, &run.index.to_string()),
+                );
+                script
+            })
+            .collect()
+    };
+
+    println!(
+        "Written {} script(s) for {} runs in {}",
+        scripts.len(),
+        runs.len(),
+        args.scripts_dir.display()
+    );
+
+    if args.submit {
+        for script in &scripts {
+            sbatch(&args, script);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    const TOOLS: &str = r#"
+[[tools]]
+name = "tool-a"
+path = "tool-a"
+arguments = "<INPUT_FILES> -o <OUTPUT_FILE>"
+
+[[tools]]
+name = "tool-b"
+path = "tool-b"
+arguments = "<INPUT_FILES> -o <OUTPUT_FILE>"
+"#;
+
+    const BENCHMARKS: &str = r#"
+[[benchmarks]]
+name = "slurm"
+datasets = ["small"]
+tools = ["tool-a", "tool-b"]
+working-dirs = ["scratch"]
+copy-dataset = false
+kvalues = [31]
+threads = [4, 16]
+max-memory = 8
+min-multiplicity = 1
+size-check-time = 1000
+
+[[benchmarks]]
+name = "memory"
+datasets = ["small"]
+tools = ["tool-a"]
+working-dirs = ["scratch"]
+copy-dataset = false
+kvalues = [31]
+threads = [4]
+max-memory = 8
+min-multiplicity = 1
+size-check-time = 1000
+[benchmarks.sweep]
+max-memory = [4, 32]
+[benchmarks.memory-search]
+lower-gb = 1
+upper-gb = 64
+"#;
+
+    const LOCAL: &str = r#"
+[[datasets]]
+name = "small"
+files = ["small.fa"]
+
+[[working-dirs]]
+name = "scratch"
+path = "scratch"
+"#;
+
+    /// Writes the configs and an sbatch stand-in recording its arguments in sbatch-calls.txt
+    fn setup(name: &str) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("submit-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        create_dir_all(&dir).unwrap();
+
+        std::fs::write(dir.join("tools.toml"), TOOLS).unwrap();
+        std::fs::write(dir.join("benchmarks.toml"), BENCHMARKS).unwrap();
+        std::fs::write(dir.join("local.toml"), LOCAL).unwrap();
+
+        let sbatch = dir.join("sbatch");
+        std::fs::write(
+            &sbatch,
+            format!(
+                "#!/bin/sh\necho \"$@\" >> {}\n",
+                dir.join("sbatch-calls.txt").display()
+            ),
+        )
+        .unwrap();
+        std::fs::set_permissions(&sbatch, std::fs::Permissions::from_mode(0o755)).unwrap();
+        dir
+    }
+
+    fn cli(dir: &Path, benchmark: &str, extra: &[&str]) -> SubmitCli {
+        let path = |name: &str| dir.join(name).to_str().unwrap().to_string();
+        let mut args = vec![
+            "submit".to_string(),
+            benchmark.to_string(),
+            path("results"),
+            "-b".to_string(),
+            path("benchmarks.toml"),
+            "-t".to_string(),
+            path("tools.toml"),
+            "-e".to_string(),
+            path("local.toml"),
+            "--scripts-dir".to_string(),
+            path("scripts"),
+            "--submit".to_string(),
+            "--sbatch".to_string(),
+            path("sbatch"),
+        ];
+        args.extend(extra.iter().map(|arg| arg.to_string()));
+        SubmitCli::from_iter(args)
+    }
+
+    /// Base name of the run selected by the bench subcommand with --run-index
+    fn selected_run(dir: &Path, index: usize) -> (String, PathBuf) {
+        let (config, _, _) = load_config(
+            &dir.join("benchmarks.toml"),
+            &dir.join("tools.toml"),
+            &dir.join("local.toml"),
+        );
+        let mut matrix = RunMatrix::new(&config, &config.benchmarks[0], &None, &None, &None);
+        matrix.select_run(index);
+        let runs = matrix.runs();
+        assert_eq!(runs.len(), 1);
+        (runs[0].base_name.clone(), runs[0].working_dir.path.clone())
+    }
+
+    #[test]
+    fn one_script_per_run() {
+        let dir = setup("runs");
+        submit(cli(&dir, "slurm", &[]));
+
+        let mut scripts: Vec<_> = std::fs::read_dir(dir.join("scripts"))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        scripts.sort();
+        assert_eq!(scripts.len(), 4);
+
+        let calls = std::fs::read_to_string(dir.join("sbatch-calls.txt")).unwrap();
+        assert_eq!(calls.lines().count(), 4);
+
+        let mut working_dirs = HashSet::new();
+        for (index, script) in scripts.iter().enumerate() {
+            assert!(calls.contains(script.to_str().unwrap()));
+
+            let content = std::fs::read_to_string(script).unwrap();
+            let (base_name, working_dir) = selected_run(&dir, index);
+            assert_eq!(
+                script.file_name().unwrap().to_str().unwrap(),
+                format!("{}-{}.sh", index, base_name)
+            );
+            assert!(content.starts_with("#!/bin/bash\n"));
+            assert!(content.contains(&format!("#SBATCH --job-name=slurm-{}\n", base_name)));
+            assert!(content.contains("#SBATCH --mem=8192\n"));
+            assert!(content.contains(&format!(" --run-index {}\n", index)));
+
+            let threads = if base_name.ends_with("_T4") { 4 } else { 16 };
+            assert!(content.contains(&format!("#SBATCH --cpus-per-task={}\n", threads)));
+
+            assert_eq!(working_dir, Path::new("scratch").join(&base_name));
+            working_dirs.insert(working_dir);
+        }
+        // The jobs can run concurrently without sharing a working dir
+        assert_eq!(working_dirs.len(), 4);
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn job_array() {
+        let dir = setup("array");
+        submit(cli(&dir, "slurm", &["--array"]));
+
+        let script = dir.join("scripts").join("slurm-array.sh");
+        let content = std::fs::read_to_string(&script).unwrap();
+        assert!(content.contains("#SBATCH --array=0-3\n"));
+        assert!(content.contains("#SBATCH --cpus-per-task=16\n"));
+        assert!(content.contains(" --run-index \"$SLURM_ARRAY_TASK_ID\"\n"));
+
+        let calls = std::fs::read_to_string(dir.join("sbatch-calls.txt")).unwrap();
+        assert_eq!(calls.trim(), script.to_str().unwrap());
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+
+    fn script_contents(dir: &Path) -> Vec<String> {
+        let mut scripts: Vec<_> = std::fs::read_dir(dir.join("scripts"))
+            .unwrap()
+            .map(|entry| entry.unwrap().path())
+            .collect();
+        scripts.sort();
+        scripts
+            .iter()
+            .map(|script| std::fs::read_to_string(script).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn memory_of_the_run() {
+        let dir = setup("memory");
+        submit(cli(&dir, "memory", &[]));
+        let contents = script_contents(&dir);
+        assert!(contents[0].contains("#SBATCH --mem=4096\n"));
+        assert!(contents[1].contains("#SBATCH --mem=32768\n"));
+
+        std::fs::remove_dir_all(dir.join("scripts")).unwrap();
+        submit(cli(&dir, "memory", &["--memory-search", "--array"]));
+        let contents = script_contents(&dir);
+        assert!(contents[0].contains("#SBATCH --mem=65536\n"));
+        assert!(contents[0].contains(" --memory-search --run-index "));
+
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,100 @@
+use crate::runner::{RunOutcome, RunResults};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+
+/// Statistical summary of a single metric over all the repetitions of a run
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MetricSummary {
+    pub mean: f64,
+    pub median: f64,
+    pub std_dev: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunsSummary {
+    pub repetitions: usize,
+    pub warmup_runs: usize,
+    /// Repetitions that did not complete, by outcome. They are excluded from the metrics
+    #[serde(default)]
+    pub incomplete_runs: BTreeMap<RunOutcome, usize>,
+    /// Metrics of the completed repetitions
+    pub metrics: BTreeMap<String, MetricSummary>,
+}
+
+fn median(sorted: &[f64]) -> f64 {
+    let len = sorted.len();
+    if len % 2 == 0 {
+        (sorted[len / 2 - 1] + sorted[len / 2]) / 2.0
+    } else {
+        sorted[len / 2]
+    }
+}
+
+impl MetricSummary {
+    pub fn from_samples(samples: &[f64]) -> Self {
+        assert!(samples.len() > 0);
+
+        let mut sorted = samples.to_vec();
+        sorted.sort_by(|a, b| a.total_cmp(b));
+
+        let count = sorted.len() as f64;
+        let mean = sorted.iter().sum::<f64>() / count;
+
+        // Sample standard deviation, zero when only one run is available
+        let std_dev = if sorted.len() > 1 {
+            (sorted.iter().map(|x| (x - mean).powi(2)).sum::<f64>() / (count - 1.0)).sqrt()
+        } else {
+            0.0
+        };
+
+        Self {
+            mean,
+            median: median(&sorted),
+            std_dev,
+            min: sorted[0],
+            max: sorted[sorted.len() - 1],
+        }
+    }
+}
+
+impl RunsSummary {
+    pub fn from_runs(runs: &[RunResults], warmup_runs: usize) -> Self {
+        let mut samples: BTreeMap<String, Vec<f64>> = BTreeMap::new();
+        let mut incomplete_runs = BTreeMap::new();
+
+        for run in runs {
+            if run.outcome != RunOutcome::Completed {
+                *incomplete_runs.entry(run.outcome).or_insert(0) += 1;
+                continue;
+            }
+            for (name, value) in run.metrics() {
+                samples.entry(name.to_string()).or_default().push(value);
+            }
+        }
+
+        Self {
+            repetitions: runs.len(),
+            warmup_runs,
+            incomplete_runs,
+            metrics: samples
+                .into_iter()
+                .map(|(name, values)| (name, MetricSummary::from_samples(&values)))
+                .collect(),
+        }
+    }
+}
+
+/// Returns the index of the completed run with the median real time, used as the representative run.
+/// If no run completed, the median is taken among all of them
+pub fn median_run_index(runs: &[RunResults]) -> usize {
+    let mut indices: Vec<_> = (0..runs.len())
+        .filter(|&i| runs[i].outcome == RunOutcome::Completed)
+        .collect();
+    if indices.is_empty() {
+        indices = (0..runs.len()).collect();
+    }
+    indices.sort_by(|a, b| runs[*a].real_time_secs.total_cmp(&runs[*b].real_time_secs));
+    indices[(indices.len() - 1) / 2]
+}
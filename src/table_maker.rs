@@ -1,4 +1,5 @@
-use crate::RunResults;
+use crate::results_schema::load_results;
+use crate::runner::{RunMetadata, RunOutcome};
 use itertools::*;
 use std::borrow::Borrow;
 use std::cmp::max;
@@ -265,15 +266,6 @@ impl TableMaker {
 /*
 */
 
-#[derive(Clone, Ord, PartialOrd, Eq, PartialEq)]
-struct ParsedPath {
-    dataset: String,
-    wdir: String,
-    k: usize,
-    tool: String,
-    threads: usize,
-}
-
 fn remap(val: &str) -> String {
     REMAPPINGS
         .iter()
@@ -283,45 +275,20 @@ fn remap(val: &str) -> String {
         .to_string()
 }
 
-impl ParsedPath {
-    pub fn from_path(path: &str) -> Option<Self> {
-        if !path.ends_with("info.json") {
-            return None;
-        }
-
-        let file_name = path.split("/").last().unwrap();
-        let parts: Vec<_> = file_name.split("_").collect();
-
-        // {}_{}_K{}_{}_T{}thr-info.json
-        let dataset = parts[0].to_string();
-        let wdir = parts[1].to_string();
-        let k: usize = parts[2][1..].parse().unwrap();
-        let tool = parts[3].to_string();
-
-        let tool = tool.strip_suffix("-ref").unwrap_or(&tool);
-        let tool = tool.strip_suffix("-reads").unwrap_or(&tool);
-        let tool = tool
-            .strip_suffix(&format!("-k{}", k))
-            .unwrap_or(&tool)
-            .to_string();
-
-        let dataset = dataset
-            .strip_suffix(&format!("-{}", dataset))
-            .unwrap_or(&dataset)
-            .to_string();
-
-        let threads: usize = parts[4][1..(parts[4].len() - "thr-info.json".len())]
-            .parse()
-            .unwrap();
-
-        Some(Self {
-            dataset,
-            wdir,
-            k,
-            tool,
-            threads,
-        })
-    }
+/// Query runs and swept parameters, e.g. "query multiplicity=2"
+fn variant_label(metadata: &RunMetadata) -> String {
+    metadata
+        .query
+        .then(|| "query".to_string())
+        .into_iter()
+        .chain(
+            metadata
+                .sweep
+                .iter()
+                .map(|(name, value)| format!("{}={}", name, value)),
+        )
+        .collect::<Vec<_>>()
+        .join(" ")
 }
 
 pub fn make_table(args: TableMakerCli) {
@@ -339,30 +306,32 @@ pub fn make_table(args: TableMakerCli) {
 
     let mut table_maker = TableMaker::new();
 
-    content.retain(|p| ParsedPath::from_path(p).is_some());
-    content.sort_by_cached_key(|p| ParsedPath::from_path(p).unwrap());
+    content.retain(|p| p.ends_with("thr-info.json"));
+    let mut all_results: Vec<_> = content
+        .iter()
+        .map(|file| load_results(Path::new(file)))
+        .collect();
+    all_results.sort_by_cached_key(|results| {
+        let metadata = &results.metadata;
+        (
+            metadata.dataset.clone(),
+            metadata.working_dir.clone(),
+            metadata.k,
+            metadata.tool_group.clone(),
+            metadata.threads,
+            variant_label(metadata),
+        )
+    });
 
     for target_dataset in args.datasets.split(",") {
         let start_row = table_maker.row_labels.len();
 
-        for file in &content {
-            if !file.ends_with("info.json") {
+        for results in &all_results {
+            let metadata = &results.metadata;
+            if metadata.dataset != target_dataset {
                 continue;
             }
-
-            let ParsedPath {
-                dataset,
-                wdir,
-                k,
-                tool,
-                threads,
-            } = ParsedPath::from_path(&file).unwrap();
-
-            if dataset != target_dataset {
-                continue;
-            }
-
-            let results: RunResults = serde_json::from_reader(File::open(&file).unwrap()).unwrap();
+            let variant = variant_label(metadata);
 
             let hours = (results.real_time_secs / 3600.0) as usize;
             let minutes = ((results.real_time_secs / 60.0) % 60.0) as usize;
@@ -375,22 +344,52 @@ pub fn make_table(args: TableMakerCli) {
             };
 
             table_maker.add_sample(
-                &remap(&dataset),
-                &k.to_string(),
-                &remap(&tool),
-                if results.has_completed {
-                    (
+                &remap(&metadata.dataset),
+                &metadata.k.to_string(),
+                &if variant.is_empty() {
+                    remap(&metadata.tool_group)
+                } else {
+                    format!("{} ({})", remap(&metadata.tool_group), variant)
+                },
+                match results.outcome {
+                    RunOutcome::Completed => (
                         duration_string,
                         Some(format!("{:.2}GB", results.max_memory_gb)),
-                    )
-                } else {
-                    ("crashed".to_string(), None)
+                    ),
+                    RunOutcome::TimedOut => ("timeout".to_string(), None),
+                    RunOutcome::Interrupted => ("interrupted".to_string(), None),
+                    RunOutcome::OutOfMemory => (
+                        "OOM".to_string(),
+                        results
+                            .memory_limit_gb
+                            .map(|limit| format!("{:.2}GB", limit)),
+                    ),
+                    RunOutcome::DiskLimit => (
+                        "disk limit".to_string(),
+                        results.disk_limit_gb.map(|limit| format!("{:.2}GB", limit)),
+                    ),
+                    RunOutcome::MemoryLimit => (
+                        "memory limit".to_string(),
+                        results
+                            .memory_kill_rss_gb
+                            .map(|rss| format!("{:.2}GB", rss)),
+                    ),
+                    RunOutcome::KilledBySignal => ("crashed".to_string(), results.signal.clone()),
+                    RunOutcome::Failed => (
+                        "failed".to_string(),
+                        results.exit_code.map(|code| format!("exit {}", code)),
+                    ),
                 },
             );
 
             println!(
                 "{} {} {} {} {} => {:#?}",
-                dataset, wdir, k, tool, threads, results
+                metadata.dataset,
+                metadata.working_dir,
+                metadata.k,
+                metadata.tool,
+                metadata.threads,
+                results
             );
         }
 
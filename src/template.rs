@@ -0,0 +1,587 @@
+use std::collections::HashMap;
+
+#[derive(Debug, Clone)]
+enum Condition {
+    IsSet(String),
+    IsNotSet(String),
+    Compare(String, String, String),
+}
+
+#[derive(Debug, Clone)]
+enum Node {
+    Text(String),
+    Space,
+    Variable {
+        name: String,
+        filter: Option<String>,
+        quoted: bool,
+    },
+    If {
+        condition: Condition,
+        then_nodes: Vec<Node>,
+        else_nodes: Vec<Node>,
+    },
+    For {
+        variable: String,
+        list: String,
+        body: Vec<Node>,
+    },
+}
+
+enum BlockEnd {
+    Else,
+    End,
+}
+
+enum BlockTag {
+    NotABlock,
+    Parsed,
+    End(BlockEnd),
+}
+
+enum Piece {
+    Text(String),
+    Space,
+    List(Vec<String>),
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+}
+
+fn is_variable_name(name: &str) -> bool {
+    name.len() > 0
+        && name
+            .chars()
+            .all(|c| c.is_ascii_uppercase() || c.is_ascii_digit() || c == '_')
+}
+
+impl Parser {
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    /// Parses a <NAME> or <NAME:filter> placeholder, returns None if the text at the cursor is not a placeholder
+    fn parse_placeholder(&mut self, quoted: bool) -> Option<Node> {
+        let rest: String = self.chars[self.pos..].iter().collect();
+        let end = rest.find('>')?;
+        let content = &rest[1..end];
+
+        let (name, filter) = match content.split_once(':') {
+            Some((name, filter)) => (name, Some(filter.to_string())),
+            None => (content, None),
+        };
+
+        if !is_variable_name(name) {
+            return None;
+        }
+
+        self.pos += content.chars().count() + 2;
+        Some(Node::Variable {
+            name: name.to_string(),
+            filter,
+            quoted,
+        })
+    }
+
+    fn parse_condition(condition: &str) -> Result<Condition, String> {
+        let parts: Vec<_> = condition.split_whitespace().collect();
+        match parts.as_slice() {
+            [name] if name.starts_with('!') && is_variable_name(&name[1..]) => {
+                Ok(Condition::IsNotSet(name[1..].to_string()))
+            }
+            [name] if is_variable_name(name) => Ok(Condition::IsSet(name.to_string())),
+            [name, operator, value]
+                if is_variable_name(name)
+                    && ["==", "!=", "<", "<=", ">", ">="].contains(operator) =>
+            {
+                Ok(Condition::Compare(
+                    name.to_string(),
+                    operator.to_string(),
+                    value.to_string(),
+                ))
+            }
+            _ => Err(format!("Invalid condition '{}'", condition)),
+        }
+    }
+
+    /// Parses a {...} block tag, braces that do not contain a known tag are plain text
+    fn parse_block(&mut self, nodes: &mut Vec<Node>) -> Result<BlockTag, String> {
+        let rest: String = self.chars[self.pos..].iter().collect();
+        let tag = match rest.find('}') {
+            Some(end) => rest[1..end].trim().to_string(),
+            None => return Ok(BlockTag::NotABlock),
+        };
+        let keyword = tag.split_whitespace().next().unwrap_or("");
+
+        if !["if", "for", "else", "end"].contains(&keyword) {
+            return Ok(BlockTag::NotABlock);
+        }
+
+        self.pos += rest[..rest.find('}').unwrap()].chars().count() + 1;
+
+        match keyword {
+            "else" => return Ok(BlockTag::End(BlockEnd::Else)),
+            "end" => return Ok(BlockTag::End(BlockEnd::End)),
+            "if" => {
+                let condition = Self::parse_condition(&tag[2..])?;
+                let (then_nodes, end) = self.parse_sequence(true)?;
+                let else_nodes = match end {
+                    Some(BlockEnd::Else) => match self.parse_sequence(true)? {
+                        (nodes, Some(BlockEnd::End)) => nodes,
+                        _ => return Err("Missing {end} after {else}".to_string()),
+                    },
+                    Some(BlockEnd::End) => vec![],
+                    None => return Err(format!("Missing {{end}} for {{{}}}", tag)),
+                };
+                nodes.push(Node::If {
+                    condition,
+                    then_nodes,
+                    else_nodes,
+                });
+            }
+            _ => {
+                let parts: Vec<_> = tag.split_whitespace().collect();
+                match parts.as_slice() {
+                    ["for", variable, "in", list]
+                        if is_variable_name(variable) && is_variable_name(list) =>
+                    {
+                        let body = match self.parse_sequence(true)? {
+                            (body, Some(BlockEnd::End)) => body,
+                            _ => return Err(format!("Missing {{end}} for {{{}}}", tag)),
+                        };
+                        nodes.push(Node::For {
+                            variable: variable.to_string(),
+                            list: list.to_string(),
+                            body,
+                        });
+                    }
+                    _ => return Err(format!("Invalid loop '{{{}}}'", tag)),
+                }
+            }
+        }
+        Ok(BlockTag::Parsed)
+    }
+
+    fn parse_sequence(&mut self, in_block: bool) -> Result<(Vec<Node>, Option<BlockEnd>), String> {
+        let mut nodes = vec![];
+
+        while let Some(c) = self.peek() {
+            match c {
+                c if c.is_whitespace() => {
+                    while self.peek().map(|c| c.is_whitespace()).unwrap_or(false) {
+                        self.pos += 1;
+                    }
+                    nodes.push(Node::Space);
+                }
+                '\'' => {
+                    self.pos += 1;
+                    let mut text = String::new();
+                    loop {
+                        match self.peek() {
+                            Some('\'') => break,
+                            Some(c) => text.push(c),
+                            None => return Err("Unterminated single quote".to_string()),
+                        }
+                        self.pos += 1;
+                    }
+                    self.pos += 1;
+                    nodes.push(Node::Text(text));
+                }
+                '"' => {
+                    self.pos += 1;
+                    // Ensures that an empty string still produces an argument
+                    nodes.push(Node::Text(String::new()));
+                    loop {
+                        match self.peek() {
+                            Some('"') => break,
+                            Some('\\') if self.chars.get(self.pos + 1).is_some() => {
+                                nodes.push(Node::Text(self.chars[self.pos + 1].to_string()));
+                                self.pos += 2;
+                            }
+                            Some('<') => match self.parse_placeholder(true) {
+                                Some(node) => nodes.push(node),
+                                None => {
+                                    nodes.push(Node::Text("<".to_string()));
+                                    self.pos += 1;
+                                }
+                            },
+                            Some(c) => {
+                                nodes.push(Node::Text(c.to_string()));
+                                self.pos += 1;
+                            }
+                            None => return Err("Unterminated double quote".to_string()),
+                        }
+                    }
+                    self.pos += 1;
+                }
+                '\\' => {
+                    let escaped = self
+                        .chars
+                        .get(self.pos + 1)
+                        .ok_or("Trailing backslash".to_string())?;
+                    nodes.push(Node::Text(escaped.to_string()));
+                    self.pos += 2;
+                }
+                '<' => match self.parse_placeholder(false) {
+                    Some(node) => nodes.push(node),
+                    None => {
+                        nodes.push(Node::Text("<".to_string()));
+                        self.pos += 1;
+                    }
+                },
+                '{' => match self.parse_block(&mut nodes)? {
+                    BlockTag::End(end) if in_block => return Ok((nodes, Some(end))),
+                    BlockTag::End(_) => return Err("Unexpected {else} or {end}".to_string()),
+                    BlockTag::Parsed => {}
+                    BlockTag::NotABlock => {
+                        nodes.push(Node::Text("{".to_string()));
+                        self.pos += 1;
+                    }
+                },
+                c => {
+                    nodes.push(Node::Text(c.to_string()));
+                    self.pos += 1;
+                }
+            }
+        }
+
+        Ok((nodes, None))
+    }
+}
+
+fn apply_filter(value: &str, filter: &str) -> Result<String, String> {
+    let number = || {
+        value
+            .parse::<f64>()
+            .map_err(|_| format!("Cannot apply filter '{}' to '{}'", filter, value))
+    };
+
+    // Memory sizes are expressed in gigabytes
+    Ok(match filter {
+        "gb" => format!("{:.2}", number()?),
+        "mb" => format!("{}", (number()? * 1024.0).round() as u64),
+        "kb" => format!("{}", (number()? * 1024.0 * 1024.0).round() as u64),
+        "bytes" => format!("{}", (number()? * 1024.0 * 1024.0 * 1024.0).round() as u64),
+        "int" => format!("{}", number()?.round() as i64),
+        _ => return Err(format!("Unknown filter '{}'", filter)),
+    })
+}
+
+fn lookup<'a>(
+    variables: &'a HashMap<String, Vec<String>>,
+    name: &str,
+) -> Result<&'a Vec<String>, String> {
+    variables
+        .get(name)
+        .ok_or_else(|| format!("Unknown variable <{}>", name))
+}
+
+fn evaluate_condition(
+    condition: &Condition,
+    variables: &HashMap<String, Vec<String>>,
+) -> Result<bool, String> {
+    let is_set = |name: &str| -> Result<bool, String> {
+        Ok(lookup(variables, name)?
+            .first()
+            .map(|v| !["", "0", "false"].contains(&v.as_str()))
+            .unwrap_or(false))
+    };
+
+    Ok(match condition {
+        Condition::IsSet(name) => is_set(name)?,
+        Condition::IsNotSet(name) => !is_set(name)?,
+        Condition::Compare(name, operator, value) => {
+            let current = lookup(variables, name)?
+                .first()
+                .cloned()
+                .unwrap_or_default();
+            let ordering = match (current.parse::<f64>(), value.parse::<f64>()) {
+                (Ok(current), Ok(value)) => current.partial_cmp(&value),
+                _ => Some(current.as_str().cmp(value.as_str())),
+            };
+            match (operator.as_str(), ordering) {
+                (_, None) => false,
+                ("==", Some(ordering)) => ordering.is_eq(),
+                ("!=", Some(ordering)) => ordering.is_ne(),
+                ("<", Some(ordering)) => ordering.is_lt(),
+                ("<=", Some(ordering)) => ordering.is_le(),
+                (">", Some(ordering)) => ordering.is_gt(),
+                (_, Some(ordering)) => ordering.is_ge(),
+            }
+        }
+    })
+}
+
+fn evaluate(
+    nodes: &[Node],
+    variables: &HashMap<String, Vec<String>>,
+    pieces: &mut Vec<Piece>,
+) -> Result<(), String> {
+    for node in nodes {
+        match node {
+            Node::Text(text) => pieces.push(Piece::Text(text.clone())),
+            Node::Space => pieces.push(Piece::Space),
+            Node::Variable {
+                name,
+                filter,
+                quoted,
+            } => {
+                let values = lookup(variables, name)?
+                    .iter()
+                    .map(|value| match filter {
+                        Some(filter) => apply_filter(value, filter),
+                        None => Ok(value.clone()),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                if *quoted {
+                    pieces.push(Piece::Text(values.join(" ")));
+                } else {
+                    pieces.push(Piece::List(values));
+                }
+            }
+            Node::If {
+                condition,
+                then_nodes,
+                else_nodes,
+            } => {
+                if evaluate_condition(condition, variables)? {
+                    evaluate(then_nodes, variables, pieces)?;
+                } else {
+                    evaluate(else_nodes, variables, pieces)?;
+                }
+            }
+            Node::For {
+                variable,
+                list,
+                body,
+            } => {
+                for value in lookup(variables, list)? {
+                    let mut scope = variables.clone();
+                    scope.insert(variable.clone(), vec![value.clone()]);
+                    evaluate(body, &scope, pieces)?;
+                    // Each iteration is a separate group of arguments
+                    pieces.push(Piece::Space);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+fn assemble_word(word: Vec<Piece>, arguments: &mut Vec<String>) -> Result<(), String> {
+    let mut prefix = String::new();
+    let mut suffix = String::new();
+    let mut list = None;
+
+    for piece in word {
+        let text = match piece {
+            Piece::List(values) if values.len() != 1 => {
+                if list.is_some() {
+                    return Err("Multiple list variables in the same argument".to_string());
+                }
+                list = Some(values);
+                continue;
+            }
+            Piece::List(mut values) => values.pop().unwrap(),
+            Piece::Text(text) => text,
+            Piece::Space => unreachable!(),
+        };
+
+        if list.is_some() {
+            suffix.push_str(&text);
+        } else {
+            prefix.push_str(&text);
+        }
+    }
+
+    match list {
+        // A list expands the argument once for each value, an empty list removes it
+        Some(values) => {
+            arguments.extend(
+                values
+                    .into_iter()
+                    .map(|value| format!("{}{}{}", prefix, value, suffix)),
+            );
+        }
+        None => arguments.push(prefix),
+    }
+    Ok(())
+}
+
+/// Renders a tool arguments template to the list of command line arguments.
+///
+/// Arguments are split on runs of whitespace like in a shell: single quotes keep their content verbatim,
+/// double quotes and backslashes keep text in a single argument while still substituting placeholders.
+/// As in a shell the quotes themselves are removed, `{"json": 1}` gives `{json:` and `1}`: wrap it in
+/// single quotes to pass it unchanged.
+/// - `<NAME>` is replaced by the value of a variable, a list variable outside quotes repeats the enclosing argument
+///   for each value (`-i<INPUT_FILES>.gz` gives `-ix.gz -iy.gz`) and removes it if empty, inside quotes the values
+///   are joined by spaces
+/// - `<NAME:filter>` applies a filter to the value: `gb`, `mb`, `kb`, `bytes` convert a size in GB, `int` rounds
+/// - `{if COND}...{else}...{end}` keeps a fragment only if `COND` holds. `COND` is `NAME`, `!NAME` or `NAME OP VALUE`, with `OP` in `== != < <= > >=`
+/// - `{for NAME in LIST}...{end}` repeats a fragment for each value of `LIST`, binding `<NAME>` to the value
+pub fn render_arguments(
+    template: &str,
+    variables: &HashMap<String, Vec<String>>,
+) -> Result<Vec<String>, String> {
+    let mut parser = Parser {
+        chars: template.chars().collect(),
+        pos: 0,
+    };
+    let (nodes, _) = parser.parse_sequence(false)?;
+
+    let mut pieces = vec![];
+    evaluate(&nodes, variables, &mut pieces)?;
+
+    let mut arguments = vec![];
+    let mut word = vec![];
+    for piece in pieces {
+        match piece {
+            Piece::Space => {
+                if !word.is_empty() {
+                    assemble_word(std::mem::take(&mut word), &mut arguments)?;
+                }
+            }
+            piece => word.push(piece),
+        }
+    }
+    if !word.is_empty() {
+        assemble_word(word, &mut arguments)?;
+    }
+
+    Ok(arguments)
+}
+
+/// Quotes an argument so that it can be pasted in a shell or parsed back as a template
+pub fn shell_quote(argument: &str) -> String {
+    if argument.len() > 0
+        && argument
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_-./=:,+@%".contains(c))
+    {
+        argument.to_string()
+    } else {
+        format!("'{}'", argument.replace('\'', "'\\''"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(template: &str) -> Result<Vec<String>, String> {
+        let variables: HashMap<String, Vec<String>> = [
+            ("OUTPUT_FILE", vec!["out.fa"]),
+            ("TEMP_DIR", vec!["/tmp/x y"]),
+            ("INPUT_FILES", vec!["a.fa", "b.fa"]),
+            ("EMPTY_LIST", vec![]),
+            ("MULTIPLICITY", vec!["2"]),
+            ("MAX_MEMORY", vec!["1.5"]),
+        ]
+        .iter()
+        .map(|(name, values)| {
+            (
+                name.to_string(),
+                values.iter().map(|v| v.to_string()).collect(),
+            )
+        })
+        .collect();
+        render_arguments(template, &variables)
+    }
+
+    fn args(arguments: &[&str]) -> Result<Vec<String>, String> {
+        Ok(arguments.iter().map(|a| a.to_string()).collect())
+    }
+
+    #[test]
+    fn double_spaces_do_not_produce_empty_arguments() {
+        // From the cuttlefish2 templates in tools.toml
+        assert_eq!(
+            render("-o <OUTPUT_FILE>  -w <TEMP_DIR>"),
+            args(&["-o", "out.fa", "-w", "/tmp/x y"])
+        );
+    }
+
+    #[test]
+    fn embedded_placeholders() {
+        assert_eq!(render("--out=<OUTPUT_FILE>"), args(&["--out=out.fa"]));
+        assert_eq!(
+            render("-i<INPUT_FILES>.gz"),
+            args(&["-ia.fa.gz", "-ib.fa.gz"])
+        );
+        assert_eq!(render("\"<INPUT_FILES>\""), args(&["a.fa b.fa"]));
+    }
+
+    #[test]
+    fn empty_list_removes_the_argument() {
+        assert_eq!(render("--opt=<EMPTY_LIST> -k 3"), args(&["-k", "3"]));
+    }
+
+    #[test]
+    fn quotes_are_removed_like_in_a_shell() {
+        assert_eq!(render(r#"{"json": 1}"#), args(&["{json:", "1}"]));
+        assert_eq!(render(r#"'{"json": 1}'"#), args(&[r#"{"json": 1}"#]));
+        assert_eq!(render(r#""""#), args(&[""]));
+    }
+
+    #[test]
+    fn filters() {
+        assert_eq!(
+            render("<MAX_MEMORY:mb> <MAX_MEMORY:gb> <MAX_MEMORY:int>"),
+            args(&["1536", "1.50", "2"])
+        );
+        assert_eq!(
+            render("<MAX_MEMORY:tb>"),
+            Err("Unknown filter 'tb'".to_string())
+        );
+    }
+
+    #[test]
+    fn conditionals_and_loops() {
+        assert_eq!(
+            render("{if MULTIPLICITY > 1}-c <MULTIPLICITY>{else}--all{end} -x"),
+            args(&["-c", "2", "-x"])
+        );
+        assert_eq!(
+            render("{if MULTIPLICITY > 2}-c <MULTIPLICITY>{else}--all{end}"),
+            args(&["--all"])
+        );
+        assert_eq!(
+            render("{for FILE in INPUT_FILES}-s <FILE>{end}"),
+            args(&["-s", "a.fa", "-s", "b.fa"])
+        );
+    }
+
+    #[test]
+    fn errors() {
+        assert_eq!(
+            render("{if MULTIPLICITY}-c"),
+            Err("Missing {end} for {if MULTIPLICITY}".to_string())
+        );
+        assert_eq!(
+            render("{for FILE in INPUT_FILES}-s <FILE>"),
+            Err("Missing {end} for {for FILE in INPUT_FILES}".to_string())
+        );
+        assert_eq!(
+            render("-k {end}"),
+            Err("Unexpected {else} or {end}".to_string())
+        );
+        assert_eq!(
+            render("<UNKNOWN>"),
+            Err("Unknown variable <UNKNOWN>".to_string())
+        );
+    }
+
+    #[test]
+    fn shell_quote_round_trips() {
+        for argument in ["plain", "/tmp/x y", "it's", ""] {
+            assert_eq!(
+                render_arguments(&shell_quote(argument), &HashMap::new()),
+                args(&[argument])
+            );
+        }
+    }
+}